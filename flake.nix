@@ -28,6 +28,7 @@
             cargoLock = {
               lockFile = ./Cargo.lock;
             };
+            buildFeatures = [ "cli" ];
           };
           deb = pkgs.stdenv.mkDerivation {
             name = "l8r";
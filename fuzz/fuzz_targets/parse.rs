@@ -0,0 +1,25 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use l8r::haproxy::FIELD_NAMES;
+use l8r::{HaproxyLogEntry, MonthLocale};
+
+// Everything a formatter may call on a parsed entry has to hold up on arbitrary input too
+fuzz_target!(|data: &[u8]| {
+    let Ok(line) = std::str::from_utf8(data) else {
+        return
+    };
+    let Ok(mut entry) = HaproxyLogEntry::parse(line) else {
+        return
+    };
+
+    let _ = entry.colorless();
+    let _ = entry.colorize();
+    let _ = serde_json::to_string(&entry).unwrap();
+    let _ = entry.month_number(MonthLocale::French);
+    for name in FIELD_NAMES {
+        let _ = entry.field(name);
+    }
+    entry.parse_query();
+    entry.strip_query();
+});
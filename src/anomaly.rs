@@ -0,0 +1,31 @@
+use std::sync::Mutex;
+use hdrhistogram::Histogram;
+
+// Samples needed before the estimated percentile is trusted enough to flag anything
+pub const WARMUP_SAMPLES: u64 = 100;
+
+pub struct AnomalyDetector {
+    histogram: Mutex<Histogram<u64>>,
+    quantile: f64,
+}
+
+impl AnomalyDetector {
+    pub fn new(percentile: f64) -> Result<Self, Box<dyn std::error::Error>> {
+        if !(0.0..=100.0).contains(&percentile) {
+            return Err("Anomaly percentile must be between 0 and 100".into());
+        }
+
+        Ok(AnomalyDetector {
+            histogram: Mutex::new(Histogram::new(3)?),
+            quantile: percentile / 100.0,
+        })
+    }
+
+    // Records the value and reports whether it exceeds the percentile of everything seen before it
+    pub fn observe(&self, value: u64) -> bool {
+        let mut histogram = self.histogram.lock().unwrap();
+        let anomalous = histogram.len() >= WARMUP_SAMPLES && value > histogram.value_at_quantile(self.quantile);
+        histogram.saturating_record(value);
+        anomalous
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use colored::Colorize;
+use serde::Serialize;
+
+use l8r::HaproxyLogEntry;
+use crate::utils::bold;
+
+#[derive(Debug, Default, Serialize)]
+pub struct ApdexCounts {
+    pub satisfied: u64,
+    pub tolerating: u64,
+    pub frustrated: u64,
+}
+
+impl ApdexCounts {
+    fn add(&mut self, total: u64, threshold: u64) {
+        if total <= threshold {
+            self.satisfied += 1;
+        } else if total <= threshold * 4 {
+            self.tolerating += 1;
+        } else {
+            self.frustrated += 1;
+        }
+    }
+
+    fn merge(&mut self, other: ApdexCounts) {
+        self.satisfied += other.satisfied;
+        self.tolerating += other.tolerating;
+        self.frustrated += other.frustrated;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.satisfied + self.tolerating + self.frustrated
+    }
+
+    pub fn score(&self) -> Option<f64> {
+        match self.total() {
+            0 => None,
+            total => Some((self.satisfied as f64 + self.tolerating as f64 / 2.0) / total as f64),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Apdex {
+    pub overall: ApdexCounts,
+    pub backends: BTreeMap<String, ApdexCounts>,
+}
+
+impl Apdex {
+    pub fn add(&mut self, entry: &HaproxyLogEntry, threshold: u64) {
+        self.overall.add(entry.timers.total, threshold);
+        self.backends.entry(entry.backend_name.to_string()).or_default().add(entry.timers.total, threshold);
+    }
+
+    pub fn merge(mut self, other: Apdex) -> Apdex {
+        self.overall.merge(other.overall);
+        for (backend, counts) in other.backends {
+            self.backends.entry(backend).or_default().merge(counts);
+        }
+        self
+    }
+}
+
+// The usual Apdex rating bands
+fn rating(score: f64) -> &'static str {
+    match score {
+        s if s >= 0.94 => "excellent",
+        s if s >= 0.85 => "good",
+        s if s >= 0.70 => "fair",
+        s if s >= 0.50 => "poor",
+        _ => "unacceptable",
+    }
+}
+
+fn format_row(name: &str, counts: &ApdexCounts, color: bool) -> String {
+    let (score, rating) = match counts.score() {
+        Some(score) => (format!("{:.3}", score), rating(score)),
+        None => ("-".to_string(), "-"),
+    };
+    let rating_text = format!("{:<12}", rating);
+    let rating_text = match (color, rating) {
+        (false, _) => rating_text,
+        (true, "excellent") | (true, "good") => rating_text.green().to_string(),
+        (true, "fair") => rating_text.yellow().to_string(),
+        (true, "-") => rating_text,
+        (true, _) => rating_text.red().to_string(),
+    };
+
+    format!("{:<32} {:>7} {} {:>10} {:>10} {:>10} {:>10}",
+        name, score, rating_text, counts.satisfied, counts.tolerating, counts.frustrated, counts.total())
+}
+
+pub fn output_apdex(apdex: &Apdex, threshold: u64, color: bool) -> String {
+    let mut result = "".to_string();
+    let header = format!("{:<32} {:>7} {:<12} {:>10} {:>10} {:>10} {:>10}",
+        format!("Apdex (T = {}ms)", threshold), "Score", "Rating", "Satisfied", "Tolerating", "Frustrated", "Total");
+    result.push_str(&format!("{}\n", bold(&header, color)));
+    result.push_str(&format!("{}\n", format_row("all", &apdex.overall, color)));
+    for (backend, counts) in &apdex.backends {
+        result.push_str(&format!("{}\n", format_row(backend, counts, color)));
+    }
+    result
+}
+
+fn counts_json(counts: &ApdexCounts) -> serde_json::Value {
+    let mut value = serde_json::to_value(counts).unwrap();
+    value["total"] = counts.total().into();
+    value["score"] = counts.score().into();
+    value
+}
+
+pub fn output_apdex_json(apdex: &Apdex, threshold: u64) -> String {
+    let mut value = counts_json(&apdex.overall);
+    value["threshold_ms"] = threshold.into();
+    value["backends"] = apdex.backends.iter()
+        .map(|(backend, counts)| (backend.clone(), counts_json(counts)))
+        .collect::<serde_json::Map<String, serde_json::Value>>()
+        .into();
+    serde_json::to_string(&value).unwrap()
+}
@@ -0,0 +1,51 @@
+use serde::Serialize;
+
+use l8r::HaproxyLogEntry;
+use crate::utils::bold;
+
+#[derive(Debug, Default, Serialize)]
+pub struct ByteTotal {
+    pub entries: u64,
+    pub bytes_read: u64,
+    // Entries logged with `-` instead of a byte count, left out of `bytes_read`
+    pub bytes_unknown: u64,
+    // Some counts were lower bounds, so `bytes_read` is one too
+    pub incomplete: bool,
+}
+
+impl ByteTotal {
+    pub fn add(&mut self, entry: &HaproxyLogEntry) {
+        self.entries += 1;
+        match entry.bytes_read {
+            Some(bytes) => self.bytes_read += bytes,
+            None => self.bytes_unknown += 1,
+        }
+        self.incomplete |= entry.bytes_read_incomplete;
+    }
+
+    pub fn scale(&mut self, scale: impl Fn(u64) -> u64) {
+        self.entries = scale(self.entries);
+        self.bytes_read = scale(self.bytes_read);
+        self.bytes_unknown = scale(self.bytes_unknown);
+    }
+
+    pub fn merge(mut self, other: ByteTotal) -> ByteTotal {
+        self.entries += other.entries;
+        self.bytes_read += other.bytes_read;
+        self.bytes_unknown += other.bytes_unknown;
+        self.incomplete |= other.incomplete;
+        self
+    }
+}
+
+pub fn output_byte_total(total: &ByteTotal, color: bool) -> String {
+    let label = "Bytes read:";
+    format!("{} {}{} across {} entries ({} without a byte count)\n",
+        bold(label, color),
+        if total.incomplete { "at least " } else { "" },
+        total.bytes_read, total.entries, total.bytes_unknown)
+}
+
+pub fn output_byte_total_json(total: &ByteTotal) -> String {
+    serde_json::to_string(total).unwrap()
+}
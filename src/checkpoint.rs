@@ -0,0 +1,83 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Take};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::follow::file_id;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Checkpoint {
+    // Where the first line not yet processed starts
+    offset: u64,
+    id: Option<(u64, u64)>,
+}
+
+impl Checkpoint {
+    // A missing or unreadable checkpoint starts over from the beginning
+    fn load(path: &Path) -> Checkpoint {
+        std::fs::read_to_string(path).ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        // Written next to the checkpoint and renamed, so an interrupted run can't leave half of one
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_string(self).unwrap())?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+// Lines between the checkpointed offset and the end of the file as it was when opened
+pub struct CheckpointedLines {
+    checkpoint_path: PathBuf,
+    reader: BufReader<Take<File>>,
+    checkpoint: Checkpoint,
+}
+
+impl CheckpointedLines {
+    pub fn open(file: &Path, checkpoint_path: &Path) -> std::io::Result<CheckpointedLines> {
+        let mut input = File::open(file)?;
+        let metadata = input.metadata()?;
+        let id = file_id(&metadata);
+
+        let previous = Checkpoint::load(checkpoint_path);
+        // A different file or one that shrank has been rotated or truncated since the last run
+        let offset = match previous.id == id && previous.offset <= metadata.len() {
+            true => previous.offset,
+            false => 0,
+        };
+        input.seek(SeekFrom::Start(offset))?;
+
+        Ok(CheckpointedLines {
+            checkpoint_path: checkpoint_path.to_path_buf(),
+            reader: BufReader::new(input.take(metadata.len() - offset)),
+            checkpoint: Checkpoint { offset, id },
+        })
+    }
+}
+
+impl Iterator for CheckpointedLines {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let mut buf = "".to_string();
+        match self.reader.read_line(&mut buf) {
+            // A line without its newline is still being written and is left for the next run
+            Ok(_) if buf.ends_with('\n') => {
+                self.checkpoint.offset += buf.len() as u64;
+                buf.pop();
+                if buf.ends_with('\r') {
+                    buf.pop();
+                }
+                Some(buf)
+            }
+            _ => {
+                if let Err(e) = self.checkpoint.save(&self.checkpoint_path) {
+                    eprintln!("Failed to write checkpoint {}: {}", self.checkpoint_path.display(), e);
+                }
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::Write;
+use std::sync::Arc;
+use arrow_array::builder::{StringBuilder, TimestampMillisecondBuilder, UInt16Builder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+
+use l8r::HaproxyLogEntry;
+
+// Entries buffered in the column builders before they are handed to the writer
+const BATCH_SIZE: usize = 8192;
+const ROW_GROUP_SIZE: usize = 128 * 1024;
+
+#[derive(Default)]
+struct Builders {
+    accepted_at: TimestampMillisecondBuilder,
+    host: StringBuilder,
+    program: StringBuilder,
+    pid: UInt64Builder,
+    source_ip_port: StringBuilder,
+    source_ip: StringBuilder,
+    source_port: UInt16Builder,
+    frontend_name: StringBuilder,
+    backend_name: StringBuilder,
+    server_name: StringBuilder,
+    client_request: UInt64Builder,
+    queue_wait: UInt64Builder,
+    establish: UInt64Builder,
+    server_response: UInt64Builder,
+    total: UInt64Builder,
+    response_code: UInt16Builder,
+    bytes_read: UInt64Builder,
+    termination_state: StringBuilder,
+    conn_current: UInt64Builder,
+    conn_limit: UInt64Builder,
+    conn_max: UInt64Builder,
+    conn_total: UInt64Builder,
+    conn_rejected: UInt64Builder,
+    queue_server: UInt64Builder,
+    queue_backend: UInt64Builder,
+    request: StringBuilder,
+}
+
+impl Builders {
+    fn append(&mut self, entry: &HaproxyLogEntry) {
+        // A timestamp without a zone, which Arrow keeps as the wall-clock time counted as if it were UTC
+        self.accepted_at.append_option(entry.accepted_at.map(|t| t.and_utc().timestamp_millis()));
+        self.host.append_value(entry.host);
+        self.program.append_value(entry.program);
+        self.pid.append_option(entry.pid);
+        self.source_ip_port.append_value(entry.source.raw);
+        self.source_ip.append_value(entry.source.ip);
+        self.source_port.append_value(entry.source.port);
+        self.frontend_name.append_value(entry.frontend_name);
+        self.backend_name.append_value(entry.backend_name);
+        self.server_name.append_value(entry.server_name);
+        self.client_request.append_option(entry.timers.client_request);
+        self.queue_wait.append_option(entry.timers.queue_wait);
+        self.establish.append_option(entry.timers.establish);
+        self.server_response.append_option(entry.timers.server_response);
+        self.total.append_value(entry.timers.total);
+        self.response_code.append_option(entry.response_code);
+        self.bytes_read.append_option(entry.bytes_read);
+        self.termination_state.append_value(entry.termination_state.to_string());
+        self.conn_current.append_value(entry.conn_counts.current);
+        self.conn_limit.append_value(entry.conn_counts.limit);
+        self.conn_max.append_value(entry.conn_counts.max);
+        self.conn_total.append_value(entry.conn_counts.total);
+        self.conn_rejected.append_value(entry.conn_counts.rejected);
+        self.queue_server.append_value(entry.queue.server);
+        self.queue_backend.append_value(entry.queue.backend);
+        self.request.append_option(entry.request.as_ref().map(|request| request.raw.as_ref()));
+    }
+
+    // Must stay in the same order as the fields of `schema`
+    fn finish(&mut self) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(self.accepted_at.finish()),
+            Arc::new(self.host.finish()),
+            Arc::new(self.program.finish()),
+            Arc::new(self.pid.finish()),
+            Arc::new(self.source_ip_port.finish()),
+            Arc::new(self.source_ip.finish()),
+            Arc::new(self.source_port.finish()),
+            Arc::new(self.frontend_name.finish()),
+            Arc::new(self.backend_name.finish()),
+            Arc::new(self.server_name.finish()),
+            Arc::new(self.client_request.finish()),
+            Arc::new(self.queue_wait.finish()),
+            Arc::new(self.establish.finish()),
+            Arc::new(self.server_response.finish()),
+            Arc::new(self.total.finish()),
+            Arc::new(self.response_code.finish()),
+            Arc::new(self.bytes_read.finish()),
+            Arc::new(self.termination_state.finish()),
+            Arc::new(self.conn_current.finish()),
+            Arc::new(self.conn_limit.finish()),
+            Arc::new(self.conn_max.finish()),
+            Arc::new(self.conn_total.finish()),
+            Arc::new(self.conn_rejected.finish()),
+            Arc::new(self.queue_server.finish()),
+            Arc::new(self.queue_backend.finish()),
+            Arc::new(self.request.finish()),
+        ]
+    }
+}
+
+fn schema() -> SchemaRef {
+    let string = |name: &str| Field::new(name, DataType::Utf8, false);
+    let number = |name: &str| Field::new(name, DataType::UInt64, false);
+
+    Arc::new(Schema::new(vec![
+        Field::new("accepted_at", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+        string("host"),
+        string("program"),
+        Field::new("pid", DataType::UInt64, true),
+        string("source_ip_port"),
+        string("source_ip"),
+        Field::new("source_port", DataType::UInt16, false),
+        string("frontend_name"),
+        string("backend_name"),
+        string("server_name"),
+        Field::new("timers_client_request", DataType::UInt64, true),
+        Field::new("timers_queue_wait", DataType::UInt64, true),
+        Field::new("timers_establish", DataType::UInt64, true),
+        Field::new("timers_server_response", DataType::UInt64, true),
+        number("timers_total"),
+        Field::new("response_code", DataType::UInt16, true),
+        Field::new("bytes_read", DataType::UInt64, true),
+        string("termination_state"),
+        number("conn_counts_current"),
+        number("conn_counts_limit"),
+        number("conn_counts_max"),
+        number("conn_counts_total"),
+        number("conn_counts_rejected"),
+        number("queue_server"),
+        number("queue_backend"),
+        Field::new("request", DataType::Utf8, true),
+    ]))
+}
+
+pub struct ParquetSink<W: Write + Send> {
+    schema: SchemaRef,
+    writer: ArrowWriter<W>,
+    builders: Builders,
+    buffered: usize,
+}
+
+impl<W: Write + Send> ParquetSink<W> {
+    pub fn new(out: W) -> Result<Self, Box<dyn std::error::Error>> {
+        let schema = schema();
+        let props = WriterProperties::builder().set_max_row_group_row_count(Some(ROW_GROUP_SIZE)).build();
+        Ok(ParquetSink {
+            writer: ArrowWriter::try_new(out, schema.clone(), Some(props))?,
+            schema,
+            builders: Builders::default(),
+            buffered: 0,
+        })
+    }
+
+    pub fn push(&mut self, entry: &HaproxyLogEntry) -> Result<(), Box<dyn std::error::Error>> {
+        self.builders.append(entry);
+        self.buffered += 1;
+        if self.buffered >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let batch = RecordBatch::try_new(self.schema.clone(), self.builders.finish())?;
+        self.writer.write(&batch)?;
+        self.buffered = 0;
+        Ok(())
+    }
+
+    // Writes the remaining rows and the file footer, nothing is readable until this ran
+    pub fn finish(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.buffered > 0 {
+            self.flush()?;
+        }
+        self.writer.close()?;
+        Ok(())
+    }
+}
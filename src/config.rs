@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use colored::Color;
+use regex::Regex;
+use serde::Deserialize;
+
+use l8r::{HaproxyLogEntry, Theme};
+
+// $XDG_CONFIG_HOME/l8r/<name>, falling back to ~/.config/l8r/<name>
+pub fn config_path(name: &str) -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("l8r").join(name))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HealthCheckConfig {
+    // Regexes matched against the request path, without the query string
+    pub paths: Vec<String>,
+    // Regexes matched against the client address
+    pub sources: Vec<String>,
+    // Regexes matched against the frontend and backend names
+    pub names: Vec<String>,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        HealthCheckConfig {
+            paths: vec![r"^/(healthz?|livez|readyz|ping)/?$".to_string()],
+            sources: vec![],
+            names: vec![r"(?i)health|check".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub health_checks: HealthCheckConfig,
+}
+
+impl Config {
+    // A missing file is not an error, everything just keeps its default
+    pub fn load(path: Option<&Path>) -> Result<Config, Box<dyn std::error::Error>> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match config_path("config.toml") {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e).into())
+    }
+}
+
+pub struct HealthChecks {
+    paths: Vec<Regex>,
+    sources: Vec<Regex>,
+    names: Vec<Regex>,
+}
+
+impl HealthChecks {
+    pub fn new(config: &HealthCheckConfig) -> Result<Self, regex::Error> {
+        let compile = |patterns: &[String]| patterns.iter().map(|p| Regex::new(p)).collect::<Result<Vec<Regex>, regex::Error>>();
+        Ok(HealthChecks {
+            paths: compile(&config.paths)?,
+            sources: compile(&config.sources)?,
+            names: compile(&config.names)?,
+        })
+    }
+
+    pub fn is_health_check(&self, entry: &HaproxyLogEntry) -> bool {
+        entry.path().is_some_and(|path| self.paths.iter().any(|r| r.is_match(path)))
+            || self.sources.iter().any(|r| r.is_match(entry.source.ip))
+            || self.names.iter().any(|r| r.is_match(entry.frontend_name) || r.is_match(entry.backend_name))
+    }
+}
+
+const THEME_FIELDS: &[&str] = &[
+    "text", "frontend", "backend", "server", "informational", "success", "redirection", "client_error", "server_error",
+    "termination_normal", "termination_error", "retried", "redispatched",
+    "method_get", "method_post", "method_put", "method_delete", "method_other",
+];
+
+// Color names like `magenta` or `bright blue`, or `#rrggbb`
+fn parse_color(s: &str) -> Result<Color, String> {
+    if let Some(hex) = s.strip_prefix('#') {
+        let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+        return match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Color::TrueColor { r, g, b }),
+            _ => Err(format!("Invalid color {}, expected #rrggbb", s)),
+        };
+    }
+    s.parse().map_err(|_| format!("Unknown color {}", s))
+}
+
+// ~/.config/l8r/theme.toml maps field names to colors, anything left out keeps its default
+pub fn load_theme() -> Result<Theme, Box<dyn std::error::Error>> {
+    let mut theme = Theme::default();
+    let path = match config_path("theme.toml") {
+        Some(path) if path.exists() => path,
+        _ => return Ok(theme),
+    };
+
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let colors: BTreeMap<String, String> = toml::from_str(&content)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+    for (field, color) in colors {
+        let color = parse_color(&color).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let slot = match field.as_str() {
+            "text" => &mut theme.text,
+            "frontend" => &mut theme.frontend,
+            "backend" => &mut theme.backend,
+            "server" => &mut theme.server,
+            "informational" => &mut theme.informational,
+            "success" => &mut theme.success,
+            "redirection" => &mut theme.redirection,
+            "client_error" => &mut theme.client_error,
+            "server_error" => &mut theme.server_error,
+            "termination_normal" => &mut theme.termination_normal,
+            "termination_error" => &mut theme.termination_error,
+            "retried" => &mut theme.retried,
+            "redispatched" => &mut theme.redispatched,
+            "method_get" => &mut theme.method_get,
+            "method_post" => &mut theme.method_post,
+            "method_put" => &mut theme.method_put,
+            "method_delete" => &mut theme.method_delete,
+            "method_other" => &mut theme.method_other,
+            _ => return Err(format!("{}: unknown field {}, expected one of: {}", path.display(), field, THEME_FIELDS.join(", ")).into()),
+        };
+        *slot = color;
+    }
+    Ok(theme)
+}
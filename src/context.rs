@@ -0,0 +1,56 @@
+use std::collections::VecDeque;
+
+// grep-style context around matching entries, only meaningful when entries arrive in order
+pub struct Context {
+    before: usize,
+    after: usize,
+    recent: VecDeque<String>,
+    // Entries still to be printed after the last match
+    pending_after: usize,
+    printed_any: bool,
+    // An entry was skipped since the last printed one, so the next group needs a separator
+    gap: bool,
+}
+
+impl Context {
+    pub fn new(before: usize, after: usize) -> Context {
+        Context {
+            before,
+            after,
+            recent: VecDeque::with_capacity(before + 1),
+            pending_after: 0,
+            printed_any: false,
+            gap: false,
+        }
+    }
+
+    // An entry that didn't match, returned when it falls in the after-context of a match
+    pub fn other(&mut self, line: String) -> Option<String> {
+        if self.pending_after > 0 {
+            self.pending_after -= 1;
+            return Some(line)
+        }
+
+        self.recent.push_back(line);
+        if self.recent.len() > self.before {
+            self.recent.pop_front();
+            self.gap = true;
+        }
+        None
+    }
+
+    // Everything to print for a match: a separator if needed, the before-context and the match itself
+    pub fn matched(&mut self, line: String) -> Vec<String> {
+        let mut lines = vec![];
+        if self.printed_any && self.gap {
+            lines.push("--".to_string());
+        }
+        lines.extend(self.recent.drain(..));
+        lines.push(line);
+
+        self.printed_any = true;
+        self.gap = false;
+        self.pending_after = self.after;
+        lines
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use chrono::DateTime;
+
+use l8r::HaproxyLogEntry;
+use crate::utils::bold;
+
+// Counts of a field's values, bucketed by window start when a window is set
+#[derive(Debug, Default)]
+pub struct Counts {
+    pub buckets: BTreeMap<Option<i64>, BTreeMap<String, u64>>,
+}
+
+impl Counts {
+    pub fn add(&mut self, entry: &HaproxyLogEntry, field: &str, window: Option<u64>) {
+        let bucket = match window {
+            Some(window) => match entry.accepted_at {
+                Some(t) => {
+                    let t = t.and_utc().timestamp();
+                    Some(t - t.rem_euclid(window as i64))
+                }
+                // Can't place it in any window
+                None => return,
+            },
+            None => None,
+        };
+        let value = entry.field(field).unwrap_or_else(|| "-".to_string());
+        *self.buckets.entry(bucket).or_default().entry(value).or_insert(0) += 1;
+    }
+
+    // Estimates of the counts every line would give, from a --sample of them
+    pub fn scale(&mut self, scale: impl Fn(u64) -> u64) {
+        for count in self.buckets.values_mut().flat_map(|values| values.values_mut()) {
+            *count = scale(*count);
+        }
+    }
+
+    pub fn merge(mut self, other: Counts) -> Counts {
+        for (bucket, values) in other.buckets {
+            let counts = self.buckets.entry(bucket).or_default();
+            for (value, count) in values {
+                *counts.entry(value).or_insert(0) += count;
+            }
+        }
+        self
+    }
+}
+
+fn format_bucket(bucket: Option<i64>) -> String {
+    match bucket.and_then(|t| DateTime::from_timestamp(t, 0)) {
+        Some(t) => t.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "-".to_string(),
+    }
+}
+
+pub fn output_counts(counts: &Counts, field: &str, windowed: bool, color: bool) -> String {
+    let mut result = "".to_string();
+
+    if !windowed {
+        // Most frequent first
+        let mut values: Vec<(&String, &u64)> = counts.buckets.values().flatten().collect();
+        values.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let width = values.iter().map(|(v, _)| v.len()).chain([field.len()]).max().unwrap_or(0);
+
+        result.push_str(&bold(&format!("{:<width$} {:>10}", field, "Count", width = width), color));
+        result.push('\n');
+        for (value, count) in values {
+            result.push_str(&format!("{:<width$} {:>10}\n", value, count, width = width));
+        }
+        return result;
+    }
+
+    // One row per window, one column per value seen in any window
+    let columns: BTreeSet<&String> = counts.buckets.values().flat_map(|v| v.keys()).collect();
+    let width = columns.iter().map(|c| c.len()).max().unwrap_or(0).max(8);
+
+    let mut header = format!("{:<19}", "Window");
+    for column in &columns {
+        header.push_str(&format!(" {:>width$}", column, width = width));
+    }
+    result.push_str(&bold(&header, color));
+    result.push('\n');
+
+    for (bucket, values) in &counts.buckets {
+        result.push_str(&format!("{:<19}", format_bucket(*bucket)));
+        for column in &columns {
+            result.push_str(&format!(" {:>width$}", values.get(*column).unwrap_or(&0), width = width));
+        }
+        result.push('\n');
+    }
+    result
+}
+
+pub fn output_counts_json(counts: &Counts, windowed: bool) -> String {
+    if !windowed {
+        let values = counts.buckets.values().next().cloned().unwrap_or_default();
+        return serde_json::to_string(&values).unwrap();
+    }
+
+    let windows: Vec<serde_json::Value> = counts.buckets.iter().map(|(bucket, values)| {
+        serde_json::json!({ "window": format_bucket(*bucket), "counts": values })
+    }).collect();
+    serde_json::to_string(&windows).unwrap()
+}
+
+// Counts for every combination of two fields' values
+#[derive(Debug, Default)]
+pub struct CrossTab {
+    pub rows: HashMap<String, HashMap<String, u64>>,
+}
+
+impl CrossTab {
+    pub fn add(&mut self, entry: &HaproxyLogEntry, row_field: &str, column_field: &str) {
+        let row = entry.field(row_field).unwrap_or_else(|| "-".to_string());
+        let column = entry.field(column_field).unwrap_or_else(|| "-".to_string());
+        *self.rows.entry(row).or_default().entry(column).or_insert(0) += 1;
+    }
+
+    pub fn scale(&mut self, scale: impl Fn(u64) -> u64) {
+        for count in self.rows.values_mut().flat_map(|columns| columns.values_mut()) {
+            *count = scale(*count);
+        }
+    }
+
+    pub fn merge(mut self, other: CrossTab) -> CrossTab {
+        for (row, columns) in other.rows {
+            let counts = self.rows.entry(row).or_default();
+            for (column, count) in columns {
+                *counts.entry(column).or_insert(0) += count;
+            }
+        }
+        self
+    }
+}
+
+pub fn output_cross_tab(tab: &CrossTab, row_field: &str, color: bool) -> String {
+    let mut result = "".to_string();
+
+    let mut rows: Vec<&String> = tab.rows.keys().collect();
+    rows.sort();
+    let columns: BTreeSet<&String> = tab.rows.values().flat_map(|c| c.keys()).collect();
+    let label_width = rows.iter().map(|r| r.len()).chain([row_field.len(), "Total".len()]).max().unwrap_or(0);
+    let width = columns.iter().map(|c| c.len()).max().unwrap_or(0).max(8);
+
+    let mut header = format!("{:<label_width$}", row_field, label_width = label_width);
+    for column in &columns {
+        header.push_str(&format!(" {:>width$}", column, width = width));
+    }
+    header.push_str(&format!(" {:>width$}", "Total", width = width));
+    result.push_str(&bold(&header, color));
+    result.push('\n');
+
+    let mut column_totals: BTreeMap<&String, u64> = BTreeMap::new();
+    for row in rows {
+        let counts = &tab.rows[row];
+        result.push_str(&format!("{:<label_width$}", row, label_width = label_width));
+        for column in &columns {
+            let count = counts.get(*column).copied().unwrap_or(0);
+            *column_totals.entry(column).or_insert(0) += count;
+            result.push_str(&format!(" {:>width$}", count, width = width));
+        }
+        result.push_str(&bold(&format!(" {:>width$}", counts.values().sum::<u64>(), width = width), color));
+        result.push('\n');
+    }
+
+    let mut footer = format!("{:<label_width$}", "Total", label_width = label_width);
+    for column in &columns {
+        footer.push_str(&format!(" {:>width$}", column_totals.get(column).unwrap_or(&0), width = width));
+    }
+    footer.push_str(&format!(" {:>width$}", column_totals.values().sum::<u64>(), width = width));
+    result.push_str(&bold(&footer, color));
+    result.push('\n');
+    result
+}
+
+pub fn output_cross_tab_json(tab: &CrossTab) -> String {
+    // Sorted keys keep the output stable between runs
+    let rows: BTreeMap<&String, BTreeMap<&String, &u64>> = tab.rows.iter()
+        .map(|(row, columns)| (row, columns.iter().collect()))
+        .collect();
+    serde_json::to_string(&rows).unwrap()
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TopCount {
+    pub count: u64,
+    // Entries without a byte count add nothing
+    pub bytes: u64,
+}
+
+type Ranked<'a> = Vec<(&'a String, TopCount)>;
+
+// Counts of a field's values per group, of which only the most frequent are reported
+#[derive(Debug, Default)]
+pub struct TopCounts {
+    pub groups: BTreeMap<Option<String>, HashMap<String, TopCount>>,
+}
+
+impl TopCounts {
+    // Entries without a value for the field, like TCP entries for a path, aren't counted
+    pub fn add(&mut self, entry: &HaproxyLogEntry, field: &str, group_by: Option<&str>) {
+        let value = match entry.field(field) {
+            Some(value) if !value.is_empty() => value,
+            _ => return,
+        };
+        let group = group_by.map(|g| entry.field(g).unwrap_or_else(|| "-".to_string()));
+        let top = self.groups.entry(group).or_default().entry(value).or_default();
+        top.count += 1;
+        top.bytes += entry.bytes_read.unwrap_or(0);
+    }
+
+    pub fn scale(&mut self, scale: impl Fn(u64) -> u64) {
+        for top in self.groups.values_mut().flat_map(|values| values.values_mut()) {
+            top.count = scale(top.count);
+            top.bytes = scale(top.bytes);
+        }
+    }
+
+    pub fn merge(mut self, other: TopCounts) -> TopCounts {
+        for (group, values) in other.groups {
+            let counts = self.groups.entry(group).or_default();
+            for (value, other) in values {
+                let top = counts.entry(value).or_default();
+                top.count += other.count;
+                top.bytes += other.bytes;
+            }
+        }
+        self
+    }
+
+    // The n most frequent values of every group, most frequent first
+    pub fn top(&self, n: usize) -> Vec<(Option<&String>, Ranked<'_>)> {
+        self.groups.iter().map(|(group, values)| {
+            let mut values: Ranked = values.iter().map(|(v, c)| (v, *c)).collect();
+            values.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+            values.truncate(n);
+            (group.as_ref(), values)
+        }).collect()
+    }
+}
+
+pub fn output_top(top: &TopCounts, n: usize, label: &str, group_by: Option<&str>, bytes: bool, color: bool) -> String {
+    let mut result = "".to_string();
+
+    let top = top.top(n);
+    let group_label = group_by.unwrap_or("");
+    let group_width = top.iter().filter_map(|(g, _)| g.map(|g| g.len())).chain([group_label.len()]).max().unwrap_or(0);
+    let width = top.iter().flat_map(|(_, values)| values.iter().map(|(v, _)| v.chars().count())).chain([label.len()]).max().unwrap_or(0);
+
+    let mut header = match group_by {
+        Some(_) => format!("{:<group_width$} {:<width$} {:>10}", group_label, label, "Count", group_width = group_width, width = width),
+        None => format!("{:<width$} {:>10}", label, "Count", width = width),
+    };
+    if bytes {
+        header.push_str(&format!(" {:>14}", "Bytes"));
+    }
+    result.push_str(&bold(&header, color));
+    result.push('\n');
+    for (group, values) in top {
+        for (value, top) in values {
+            match group {
+                Some(group) => result.push_str(&format!("{:<group_width$} {:<width$} {:>10}", group, value, top.count, group_width = group_width, width = width)),
+                None => result.push_str(&format!("{:<width$} {:>10}", value, top.count, width = width)),
+            }
+            if bytes {
+                result.push_str(&format!(" {:>14}", top.bytes));
+            }
+            result.push('\n');
+        }
+    }
+    result
+}
+
+pub fn output_top_json(top: &TopCounts, n: usize, label: &str, bytes: bool) -> String {
+    let ranked = |values: Ranked| -> Vec<serde_json::Value> {
+        values.into_iter().map(|(value, top)| match bytes {
+            true => serde_json::json!({ label: value, "count": top.count, "bytes": top.bytes }),
+            false => serde_json::json!({ label: value, "count": top.count }),
+        }).collect()
+    };
+
+    let mut top = top.top(n);
+    if let [(None, _)] = top.as_slice() {
+        return serde_json::to_string(&ranked(top.remove(0).1)).unwrap();
+    }
+    let groups: serde_json::Map<String, serde_json::Value> = top.into_iter()
+        .map(|(group, values)| (group.cloned().unwrap_or_default(), ranked(values).into()))
+        .collect();
+    serde_json::to_string(&groups).unwrap()
+}
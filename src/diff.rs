@@ -0,0 +1,259 @@
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::group::{GroupAgg, Groups};
+use crate::stats::{BackendStats, Stats};
+use crate::utils::bold;
+
+enum Unit {
+    Count,
+    Rate,
+    Millis,
+}
+
+struct Row {
+    name: String,
+    // The snake_case name of the row in the JSON output
+    key: String,
+    unit: Unit,
+    old: Option<f64>,
+    new: Option<f64>,
+    // Whether an increase should be highlighted as a regression
+    higher_is_worse: bool,
+}
+
+impl Row {
+    fn format_value(&self, value: Option<f64>) -> String {
+        match value {
+            None => "-".to_string(),
+            Some(v) => match self.unit {
+                Unit::Count => format!("{}", v as u64),
+                Unit::Rate => format!("{:.2}%", v * 100.0),
+                Unit::Millis => format!("{}ms", v as u64),
+            }
+        }
+    }
+
+    fn delta(&self) -> Option<f64> {
+        match (self.old, self.new) {
+            (Some(old), Some(new)) if old != 0.0 => Some((new - old) / old * 100.0),
+            _ => None
+        }
+    }
+
+    fn format(&self, color: bool) -> String {
+        let delta = match (self.delta(), self.old, self.new) {
+            (Some(d), _, _) => format!("{:+.1}%", d),
+            (None, Some(old), Some(new)) if old == new => "+0.0%".to_string(),
+            (None, _, Some(_)) => "new".to_string(),
+            (None, Some(_), None) => "gone".to_string(),
+            _ => "-".to_string(),
+        };
+        let delta = format!("{:>10}", delta);
+
+        let worse = match (self.old, self.new) {
+            (Some(old), Some(new)) => (new > old) == self.higher_is_worse && new != old,
+            _ => false
+        };
+        let better = match (self.old, self.new) {
+            (Some(old), Some(new)) => (new < old) == self.higher_is_worse && new != old,
+            _ => false
+        };
+
+        // Counts only say how much traffic there was, more or less of it is neither a regression nor an improvement
+        let delta = if !color || matches!(self.unit, Unit::Count) {
+            delta.normal()
+        } else if worse {
+            delta.red().bold()
+        } else if better {
+            delta.green()
+        } else {
+            delta.normal()
+        };
+
+        format!("{:<24} {:>12} {:>12} {}",
+            self.name,
+            self.format_value(self.old),
+            self.format_value(self.new),
+            delta
+        )
+    }
+}
+
+fn rows(old: Option<&BackendStats>, new: Option<&BackendStats>) -> Vec<Row> {
+    let mut rows = vec![
+        Row {
+            name: "Requests".to_string(),
+            key: "requests".to_string(),
+            unit: Unit::Count,
+            old: old.map(|s| s.total as f64),
+            new: new.map(|s| s.total as f64),
+            higher_is_worse: false,
+        },
+        Row {
+            name: "Error rate".to_string(),
+            key: "error_rate".to_string(),
+            unit: Unit::Rate,
+            old: old.map(|s| s.error_rate()),
+            new: new.map(|s| s.error_rate()),
+            higher_is_worse: true,
+        },
+        Row {
+            name: "Bytes read".to_string(),
+            key: "bytes_read".to_string(),
+            unit: Unit::Count,
+            old: old.map(|s| s.bytes_read as f64),
+            new: new.map(|s| s.bytes_read as f64),
+            higher_is_worse: false,
+        },
+        Row {
+            name: "Unknown byte counts".to_string(),
+            key: "bytes_unknown".to_string(),
+            unit: Unit::Count,
+            old: old.map(|s| s.bytes_unknown as f64),
+            new: new.map(|s| s.bytes_unknown as f64),
+            higher_is_worse: false,
+        },
+    ];
+
+    for class in ["2xx", "3xx", "4xx", "5xx"] {
+        rows.push(Row {
+            name: format!("{} rate", class),
+            key: format!("rate_{}", class),
+            unit: Unit::Rate,
+            old: old.map(|s| s.status_rate(class)),
+            new: new.map(|s| s.status_rate(class)),
+            higher_is_worse: class != "2xx",
+        });
+    }
+
+    for (name, p) in [("p50", 50.0), ("p90", 90.0), ("p99", 99.0), ("max", 100.0)] {
+        rows.push(Row {
+            name: format!("Latency {}", name),
+            key: format!("latency_{}", name),
+            unit: Unit::Millis,
+            old: old.and_then(|s| s.percentile(p)).map(|v| v as f64),
+            new: new.and_then(|s| s.percentile(p)).map(|v| v as f64),
+            higher_is_worse: true,
+        });
+    }
+
+    rows
+}
+
+pub fn output_diff(old: &Stats, new: &Stats, color: bool) -> String {
+    let mut result = "".to_string();
+    let header = format!("{:<24} {:>12} {:>12} {:>10}", "Metric", "Old", "New", "Delta");
+
+    result.push_str(&format!("{}\n", bold(&header, color)));
+    for row in rows(Some(&old.overall), Some(&new.overall)) {
+        result.push_str(&format!("{}\n", row.format(color)));
+    }
+
+    let mut backends: Vec<&String> = old.backends.keys().chain(new.backends.keys()).collect();
+    backends.sort();
+    backends.dedup();
+
+    for backend in backends {
+        let title = format!("Backend {}", backend);
+        result.push_str(&format!("\n{}\n", if color { title.yellow().bold().to_string() } else { title }));
+        for row in rows(old.backends.get(backend), new.backends.get(backend)) {
+            result.push_str(&format!("{}\n", row.format(color)));
+        }
+    }
+
+    result
+}
+
+fn rows_json(rows: Vec<Row>) -> serde_json::Map<String, serde_json::Value> {
+    rows.into_iter().map(|row| {
+        let value = serde_json::json!({ "old": row.old, "new": row.new, "delta_pct": row.delta() });
+        (row.key, value)
+    }).collect()
+}
+
+pub fn output_diff_json(old: &Stats, new: &Stats) -> String {
+    let mut backends: Vec<&String> = old.backends.keys().chain(new.backends.keys()).collect();
+    backends.sort();
+    backends.dedup();
+
+    let backends: serde_json::Map<String, serde_json::Value> = backends.into_iter()
+        .map(|backend| (backend.clone(), rows_json(rows(old.backends.get(backend), new.backends.get(backend))).into()))
+        .collect();
+    serde_json::json!({ "overall": rows_json(rows(Some(&old.overall), Some(&new.overall))), "backends": backends }).to_string()
+}
+
+// One value of the --group-by field in both files, a group missing from one counts as 0 there
+#[derive(Debug, Serialize)]
+pub struct GroupDiff {
+    pub group: String,
+    pub old: u64,
+    pub new: u64,
+    pub delta: i64,
+    pub old_errors: u64,
+    pub new_errors: u64,
+}
+
+// Groups with the most requests in the new file first
+pub fn diff_groups(old: &Groups, new: &Groups) -> Vec<GroupDiff> {
+    let mut groups: Vec<&String> = old.groups.keys().chain(new.groups.keys()).collect();
+    groups.sort();
+    groups.dedup();
+
+    let empty = GroupAgg::default();
+    let mut diffs: Vec<GroupDiff> = groups.into_iter().map(|group| {
+        let old = old.groups.get(group).unwrap_or(&empty);
+        let new = new.groups.get(group).unwrap_or(&empty);
+        GroupDiff {
+            group: group.clone(),
+            old: old.count,
+            new: new.count,
+            delta: new.count as i64 - old.count as i64,
+            old_errors: old.errors,
+            new_errors: new.errors,
+        }
+    }).collect();
+    diffs.sort_by(|a, b| b.new.cmp(&a.new).then_with(|| a.group.cmp(&b.group)));
+    diffs
+}
+
+// A group regressed when it has more errors than before, and improved with fewer
+pub fn output_group_diff(diffs: &[GroupDiff], field: &str, color: bool) -> String {
+    let width = diffs.iter().map(|d| d.group.len()).chain([field.len()]).max().unwrap_or(0);
+    let header = format!("{:<width$} {:>10} {:>10} {:>10} {:>10} {:>10}", field, "Old", "New", "Delta", "Old errors", "New errors", width = width);
+
+    let mut result = format!("{}\n", bold(&header, color));
+    for diff in diffs {
+        let delta = format!("{:>10}", format!("{:+}", diff.delta));
+        let delta = if !color {
+            delta.normal()
+        } else if diff.new_errors > diff.old_errors {
+            delta.red().bold()
+        } else if diff.new_errors < diff.old_errors {
+            delta.green()
+        } else {
+            delta.normal()
+        };
+        result.push_str(&format!("{:<width$} {:>10} {:>10} {} {:>10} {:>10}\n",
+            diff.group, diff.old, diff.new, delta, diff.old_errors, diff.new_errors, width = width));
+    }
+    result
+}
+
+pub fn output_group_diff_json(diffs: &[GroupDiff]) -> String {
+    serde_json::to_string(diffs).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_keys() {
+        let keys: Vec<String> = rows_json(rows(None, None)).into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, [
+            "bytes_read", "bytes_unknown", "error_rate", "latency_max", "latency_p50", "latency_p90", "latency_p99",
+            "rate_2xx", "rate_3xx", "rate_4xx", "rate_5xx", "requests",
+        ]);
+    }
+}
@@ -0,0 +1,43 @@
+use thiserror::Error;
+
+// Why a line couldn't be parsed into a `HaproxyLogEntry`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseError {
+    // The line doesn't have the shape of any supported log format
+    #[error("Line doesn't match any supported log format")]
+    RegexNoMatch,
+    #[error("Failed to parse timers: {raw}")]
+    BadTimers { raw: String },
+    #[error("Expected 3, 4 or 5 timers, found {count}: {raw}")]
+    TimerCount { count: usize, raw: String },
+    #[error("Termination state must be two or four flags: {raw}")]
+    BadTerminationState { raw: String },
+    // Only reported by strict parsing, lenient parsing describes the flag as unknown
+    #[error("Unknown termination flag {flag} in {raw}")]
+    UnknownTerminationFlag { flag: char, raw: String },
+    #[error("Failed to parse connection counts: {raw}")]
+    BadConnCounts { raw: String },
+    #[error("Failed to parse queue stats: {raw}")]
+    BadQueue { raw: String },
+    #[error("Failed to parse source address: {raw}")]
+    BadSourceAddress { raw: String },
+    // A single number out of range, like a status code above 65535
+    #[error("Failed to parse {field}: {raw}")]
+    BadNumber { field: &'static str, raw: String },
+}
+
+impl ParseError {
+    // A stable name for counting failures by kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::RegexNoMatch => "regex_no_match",
+            ParseError::BadTimers { .. } | ParseError::TimerCount { .. } => "timers",
+            ParseError::BadTerminationState { .. } => "termination_state",
+            ParseError::UnknownTerminationFlag { .. } => "termination_flag",
+            ParseError::BadConnCounts { .. } => "conn_counts",
+            ParseError::BadQueue { .. } => "queue",
+            ParseError::BadSourceAddress { .. } => "source_ip_port",
+            ParseError::BadNumber { field, .. } => field,
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+use regex::Regex;
+
+use l8r::HaproxyLogEntry;
+
+// Named captures of user given regexes on the request path, added to the entry as fields
+pub struct Extractor {
+    patterns: Vec<Regex>,
+}
+
+impl Extractor {
+    pub fn new(patterns: &[String]) -> Result<Extractor, String> {
+        let patterns = patterns.iter()
+            .map(|pattern| {
+                let regex = Regex::new(pattern).map_err(|e| format!("Invalid --extract regex {}: {}", pattern, e))?;
+                match regex.capture_names().flatten().next() {
+                    Some(_) => Ok(regex),
+                    None => Err(format!("--extract regex {} has no named group like (?P<id>...) to name the field", pattern)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Extractor { patterns })
+    }
+
+    // Groups that took no part in the match are left out, like a pattern that didn't match at all.
+    // A later pattern wins when two name the same field
+    pub fn extract<'e>(&self, entry: &'e HaproxyLogEntry) -> BTreeMap<String, &'e str> {
+        let mut fields = BTreeMap::new();
+        let path = match entry.path() {
+            Some(path) => path,
+            None => return fields,
+        };
+        for regex in &self.patterns {
+            if let Some(captures) = regex.captures(path) {
+                for name in regex.capture_names().flatten() {
+                    if let Some(value) = captures.name(name) {
+                        fields.insert(name.to_string(), value.as_str());
+                    }
+                }
+            }
+        }
+        fields
+    }
+}
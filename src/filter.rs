@@ -0,0 +1,304 @@
+use std::borrow::Cow;
+use chrono::NaiveDateTime;
+use regex::{Regex, RegexBuilder};
+
+use l8r::{HaproxyLogEntry, LogFormat, ParseError, Timer};
+use l8r::haproxy::{missing_groups, TERMINATION_FLAGS};
+use crate::config::{Config, HealthChecks};
+use crate::preset::Preset;
+use crate::Args;
+
+// One value of `--status`: a code like 502, a class like 5xx or an inclusive range like 500-504
+#[derive(Debug, Clone, Copy)]
+pub enum StatusMatcher {
+    Code(u16),
+    Range(u16, u16),
+}
+
+impl StatusMatcher {
+    fn matches(&self, code: u16) -> bool {
+        match *self {
+            StatusMatcher::Code(c) => code == c,
+            StatusMatcher::Range(from, to) => (from..=to).contains(&code),
+        }
+    }
+}
+
+impl std::str::FromStr for StatusMatcher {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid status {}, expected a code like 502, a class like 5xx or a range like 500-504", s);
+        let code = |s: &str| s.parse::<u16>().ok().filter(|c| (100..1000).contains(c)).ok_or_else(invalid);
+
+        let lower = s.to_ascii_lowercase();
+        if let Some(class) = lower.strip_suffix("xx") {
+            let class = class.parse::<u16>().ok().filter(|c| (1..10).contains(c)).ok_or_else(invalid)?;
+            return Ok(StatusMatcher::Range(class * 100, class * 100 + 99));
+        }
+        match s.split_once('-') {
+            Some((from, to)) => Ok(StatusMatcher::Range(code(from)?, code(to)?)),
+            None => Ok(StatusMatcher::Code(code(s)?)),
+        }
+    }
+}
+
+// One value of `--backend`, `--frontend` or `--server`, a regex when prefixed with `~`
+#[derive(Debug, Clone)]
+pub enum NameMatcher {
+    Exact(String),
+    Pattern(Regex),
+}
+
+impl NameMatcher {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NameMatcher::Exact(exact) => name == exact,
+            NameMatcher::Pattern(pattern) => pattern.is_match(name),
+        }
+    }
+}
+
+impl std::str::FromStr for NameMatcher {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('~') {
+            Some(pattern) => Ok(NameMatcher::Pattern(Regex::new(pattern)?)),
+            None => Ok(NameMatcher::Exact(s.to_string())),
+        }
+    }
+}
+
+// Any of the matchers, or every name when there are none
+fn matches_name(matchers: &[NameMatcher], name: &str) -> bool {
+    matchers.is_empty() || matchers.iter().any(|m| m.matches(name))
+}
+
+// A value parser for the `--term-*` flags of one position of the termination state,
+// each of the given flags matches
+pub fn parse_term_flags(position: usize) -> impl Fn(&str) -> Result<String, String> + Clone + Send + Sync + 'static {
+    move |s: &str| {
+        let flags = TERMINATION_FLAGS[position];
+        match s.chars().find(|flag| !flags.contains(*flag)) {
+            Some(flag) => Err(format!("Unknown flag {}, expected some of {}", flag, flags)),
+            None if s.is_empty() => Err(format!("Expected some of {}", flags)),
+            None => Ok(s.to_string()),
+        }
+    }
+}
+
+pub struct Filter {
+    preset: Option<Preset>,
+    format: Option<LogFormat>,
+    regex: Option<Regex>,
+    strict: bool,
+    normalize_paths: bool,
+    errors: bool,
+    terminations: bool,
+    matcher: Option<Regex>,
+    matcher_field: Option<String>,
+    min_bytes: Option<u64>,
+    max_bytes: Option<u64>,
+    slow: Option<(Timer, u64)>,
+    health_checks: Option<HealthChecks>,
+    status: Vec<StatusMatcher>,
+    backends: Vec<NameMatcher>,
+    frontends: Vec<NameMatcher>,
+    servers: Vec<NameMatcher>,
+    pids: Vec<u64>,
+    retried: bool,
+    redispatched: bool,
+    // The `--term-*` flags, by their position in the termination state
+    term_flags: [Option<String>; 4],
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+    invert: bool,
+}
+
+impl Filter {
+    pub fn from_args(args: &Args, config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let matcher = match &args.matcher {
+            Some(m) => Some(RegexBuilder::new(m).case_insensitive(args.matcher_ignore_case).build()?),
+            None => None
+        };
+
+        let regex = match &args.log_format_regex {
+            Some(r) => {
+                let regex = Regex::new(r)?;
+                let missing = missing_groups(&regex);
+                if !missing.is_empty() {
+                    return Err(format!("--log-format-regex is missing the named groups: {}", missing.join(", ")).into());
+                }
+                Some(regex)
+            }
+            None => None
+        };
+
+        Ok(Filter {
+            preset: args.preset,
+            format: args.format.or(args.preset.map(|preset| preset.format())),
+            regex,
+            strict: args.strict,
+            normalize_paths: args.normalize_paths,
+            errors: args.errors,
+            terminations: args.terminations,
+            matcher,
+            matcher_field: args.matcher_field.clone(),
+            min_bytes: args.min_bytes,
+            max_bytes: args.max_bytes,
+            slow: args.slow.map(|ms| (args.slow_timer, ms)),
+            health_checks: match args.exclude_health_checks {
+                true => Some(HealthChecks::new(&config.health_checks)?),
+                false => None
+            },
+            status: args.status.clone(),
+            backends: args.backend.clone(),
+            frontends: args.frontend.clone(),
+            servers: args.server.clone(),
+            pids: args.pid.clone(),
+            retried: args.retried,
+            redispatched: args.redispatched,
+            term_flags: [args.term_reason.clone(), args.term_state.clone(), args.term_cookie.clone(), args.term_operations.clone()],
+            since: args.since,
+            until: args.until,
+            invert: args.invert,
+        })
+    }
+
+    // Lines in any other format than a forced one don't parse at all
+    // What a preset maps the line into before it's parsed, the line itself for any other format
+    pub fn map_line<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        match self.preset {
+            Some(preset) => preset.map_line(line),
+            None => Cow::Borrowed(line),
+        }
+    }
+
+    // Parses `mapped`, what `map_line` made of `line`, with `line` kept as the entry's raw line
+    // so nothing prints a line that wasn't in the input
+    pub fn parse<'a>(&self, line: &'a str, mapped: &'a str) -> Result<HaproxyLogEntry<'a>, ParseError> {
+        let mut entry = match (&self.regex, self.format) {
+            (Some(regex), _) => HaproxyLogEntry::parse_with(mapped, regex),
+            (None, Some(format)) => HaproxyLogEntry::parse_as(mapped, format),
+            (None, None) => HaproxyLogEntry::parse(mapped),
+        }?;
+        entry.raw_line = line;
+        if self.strict {
+            entry.termination_state.check_flags()?;
+        }
+        Ok(entry)
+    }
+
+    // Only aggregations normalize paths, printed entries keep them as they were logged
+    pub fn normalize(&self, entry: &mut HaproxyLogEntry) {
+        if self.normalize_paths {
+            entry.normalize_path();
+        }
+    }
+
+    // Checked against the raw line, before paying for a full parse. An inverted filter
+    // or one on a field needs the whole entry, so this can't rule out any line for them
+    pub fn matches_line(&self, line: &str) -> bool {
+        self.invert || self.matcher_field.is_some() || self.matches_matcher(line)
+    }
+
+    fn matches_matcher(&self, line: &str) -> bool {
+        match self.matcher {
+            Some(ref matcher) => matcher.is_match(line),
+            None => true
+        }
+    }
+
+    // Entries without the field, like TCP entries for a request, never match
+    fn matches_field(&self, entry: &HaproxyLogEntry) -> bool {
+        match &self.matcher_field {
+            Some(field) => entry.field(field).is_some_and(|value| self.matches_matcher(&value)),
+            None => self.matches_matcher(entry.raw_line)
+        }
+    }
+
+    // `--invert` flips the combined result, not each filter on its own
+    pub fn matches(&self, entry: &HaproxyLogEntry) -> bool {
+        (self.matches_field(entry) && self.matches_entry(entry)) != self.invert
+    }
+
+    fn matches_entry(&self, entry: &HaproxyLogEntry) -> bool {
+        if self.errors && !entry.is_error() {
+            return false
+        }
+
+        if self.terminations && !entry.termination_state.is_error() {
+            return false
+        }
+
+        if !matches_name(&self.backends, entry.backend_name)
+            || !matches_name(&self.frontends, entry.frontend_name)
+            || !matches_name(&self.servers, entry.server_name) {
+            return false
+        }
+
+        if !self.pids.is_empty() && !entry.pid.is_some_and(|pid| self.pids.contains(&pid)) {
+            return false
+        }
+
+        // TCP entries have no status to match
+        if !self.status.is_empty() && !entry.response_code.is_some_and(|code| self.status.iter().any(|s| s.matches(code))) {
+            return false
+        }
+
+        if self.retried && !entry.retried {
+            return false
+        }
+
+        if self.redispatched && !entry.redispatched {
+            return false
+        }
+
+        // tcplog entries only have the first two flags, so they can't match the cookie ones
+        for (position, flags) in self.term_flags.iter().enumerate() {
+            if let Some(flags) = flags {
+                if !entry.termination_state.raw.chars().nth(position).is_some_and(|flag| flags.contains(flag)) {
+                    return false
+                }
+            }
+        }
+
+        if self.health_checks.as_ref().is_some_and(|h| h.is_health_check(entry)) {
+            return false
+        }
+
+        // Entries without a parseable accept date are outside of any time window
+        if self.since.is_some() || self.until.is_some() {
+            match entry.accepted_at {
+                Some(at) => {
+                    if self.since.is_some_and(|since| at < since) || self.until.is_some_and(|until| at >= until) {
+                        return false
+                    }
+                }
+                None => return false
+            }
+        }
+
+        // Entries without a byte count can't satisfy a byte bound either way
+        if self.min_bytes.is_some() || self.max_bytes.is_some() {
+            match entry.bytes_read {
+                Some(bytes) => {
+                    if self.min_bytes.is_some_and(|min| bytes < min) || self.max_bytes.is_some_and(|max| bytes > max) {
+                        return false
+                    }
+                }
+                None => return false
+            }
+        }
+
+        // TCP entries have no client request or server response timer to be slow in
+        if let Some((timer, ms)) = self.slow {
+            if entry.timers.get(timer).is_none_or(|value| value < ms) {
+                return false
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs::{File, Metadata};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_WAIT_INTERVAL: Duration = Duration::from_secs(5);
+
+#[cfg(unix)]
+pub fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+// Without inodes a replaced file can't be told apart from the original
+#[cfg(not(unix))]
+pub fn file_id(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn is_fifo(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    metadata.file_type().is_fifo()
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_metadata: &Metadata) -> bool {
+    false
+}
+
+// Yields lines appended to a file forever, reopening it when logrotate moves it away
+pub struct Follow {
+    path: PathBuf,
+    reader: BufReader<File>,
+    id: Option<(u64, u64)>,
+    // A line that was only partially written when it was read
+    partial: String,
+    // A named pipe, where EOF means the writer went away rather than that nothing was appended
+    fifo: bool,
+}
+
+impl Follow {
+    // Starts at the end of the file like `tail -f` without the backlog, unless the file is new.
+    // A named pipe has no backlog, opening it waits for a writer instead
+    pub fn open(path: &Path, from_start: bool) -> std::io::Result<Follow> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let id = file_id(&metadata);
+        let fifo = is_fifo(&metadata);
+        if !from_start && !fifo {
+            file.seek(SeekFrom::End(0))?;
+        }
+
+        Ok(Follow {
+            path: path.to_path_buf(),
+            reader: BufReader::new(file),
+            id,
+            partial: "".to_string(),
+            fifo,
+        })
+    }
+
+    // The path now points at a different file than the one being read
+    fn rotated(&self) -> bool {
+        match std::fs::metadata(&self.path) {
+            Ok(metadata) => file_id(&metadata).is_some_and(|id| Some(id) != self.id),
+            // Between the move and the creation of the new file
+            Err(_) => false,
+        }
+    }
+
+    // Truncated in place, like logrotate's copytruncate does, so the read position is past the end
+    fn truncated(&mut self) -> bool {
+        match (self.reader.get_ref().metadata(), self.reader.stream_position()) {
+            (Ok(metadata), Ok(position)) => metadata.len() < position,
+            _ => false,
+        }
+    }
+
+    fn reopen(&mut self) -> std::io::Result<()> {
+        let file = File::open(&self.path)?;
+        self.id = file_id(&file.metadata()?);
+        self.reader = BufReader::new(file);
+        Ok(())
+    }
+}
+
+impl Iterator for Follow {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            let mut buf = "".to_string();
+            match self.reader.read_line(&mut buf) {
+                // Reopening blocks until the next writer comes along, so there is nothing to poll
+                Ok(0) if self.fifo => {
+                    if self.reopen().is_err() {
+                        return None;
+                    }
+                }
+                Ok(0) => {
+                    // Everything left in the old file has been read by now
+                    if self.rotated() && self.reopen().is_ok() {
+                        continue;
+                    }
+                    if self.truncated() && self.reader.seek(SeekFrom::Start(0)).is_ok() {
+                        self.partial.clear();
+                        continue;
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+                Ok(_) => {
+                    self.partial.push_str(&buf);
+                    if self.partial.ends_with('\n') {
+                        let mut line = std::mem::take(&mut self.partial);
+                        line.pop();
+                        if line.ends_with('\r') {
+                            line.pop();
+                        }
+                        return Some(line);
+                    }
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+// Polls with backoff until the file exists, returns whether it had to wait at all
+pub fn wait_for_file(path: &Path, timeout: Option<Duration>) -> Result<bool, String> {
+    let start = Instant::now();
+    let mut interval = POLL_INTERVAL / 2;
+    let mut waited = false;
+    while !path.exists() {
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            return Err(format!("Timed out waiting for {} to appear", path.display()));
+        }
+        std::thread::sleep(interval);
+        interval = (interval * 2).min(MAX_WAIT_INTERVAL);
+        waited = true;
+    }
+    Ok(waited)
+}
@@ -0,0 +1,322 @@
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use lru::LruCache;
+use serde::Serialize;
+
+// Ends the data section, the metadata map follows it
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+// Between the search tree and the data section
+const DATA_SECTION_SEPARATOR: usize = 16;
+// Addresses kept looked up, the least recently seen go first on logs with more clients than this
+const CACHE_SIZE: usize = 65536;
+
+// What a lookup found, flattened into json and yaml records next to the entry's own fields
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Geo {
+    #[serde(rename = "geo_country", skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(rename = "geo_asn", skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+}
+
+// The MaxMind DB data types the lookups walk through, anything else is skipped over
+#[derive(Debug)]
+enum Value {
+    String(String),
+    Uint(u128),
+    Map(Vec<(String, Value)>),
+    Other,
+}
+
+impl Value {
+    fn get(&self, path: &[&str]) -> Option<&Value> {
+        path.iter().try_fold(self, |value, key| match value {
+            Value::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        })
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_uint(&self) -> Option<u128> {
+        match self {
+            Value::Uint(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+fn read_uint(section: &[u8], offset: usize, size: usize) -> Result<u128, String> {
+    let bytes = section.get(offset..offset + size).ok_or("Truncated MaxMind DB data")?;
+    Ok(bytes.iter().fold(0, |n, b| n << 8 | *b as u128))
+}
+
+// Decodes the value at `offset` of a section, returning it with the offset right after it.
+// Pointers are relative to the start of the section they are in
+fn decode(section: &[u8], offset: usize) -> Result<(Value, usize), String> {
+    decode_nested(section, offset, 0)
+}
+
+// Deeper than any real database nests, so a corrupt one pointing back into itself ends here
+const MAX_DEPTH: usize = 64;
+
+fn decode_nested(section: &[u8], offset: usize, depth: usize) -> Result<(Value, usize), String> {
+    if depth > MAX_DEPTH {
+        return Err("MaxMind DB data nested too deeply".into());
+    }
+    let byte = |offset: usize| section.get(offset).copied().ok_or("Truncated MaxMind DB data".to_string());
+    let control = byte(offset)?;
+    let mut offset = offset + 1;
+
+    if control >> 5 == 1 {
+        let size = ((control >> 3) & 0x3) as usize + 1;
+        let value = read_uint(section, offset, size)? as usize;
+        let pointer = match size {
+            1 => (control as usize & 0x7) << 8 | value,
+            2 => ((control as usize & 0x7) << 16 | value) + 2048,
+            3 => ((control as usize & 0x7) << 24 | value) + 526336,
+            _ => value,
+        };
+        let (value, _) = decode_nested(section, pointer, depth + 1)?;
+        return Ok((value, offset + size));
+    }
+
+    let kind = match control >> 5 {
+        0 => {
+            offset += 1;
+            byte(offset - 1)? as u16 + 7
+        }
+        kind => kind as u16,
+    };
+    let size = match control & 0x1f {
+        29 => {
+            offset += 1;
+            29 + byte(offset - 1)? as usize
+        }
+        30 => {
+            offset += 2;
+            285 + read_uint(section, offset - 2, 2)? as usize
+        }
+        31 => {
+            offset += 3;
+            65821 + read_uint(section, offset - 3, 3)? as usize
+        }
+        size => size as usize,
+    };
+    let payload = |offset: usize| section.get(offset..offset + size).ok_or("Truncated MaxMind DB data".to_string());
+
+    match kind {
+        2 => Ok((Value::String(String::from_utf8_lossy(payload(offset)?).into_owned()), offset + size)),
+        // Doubles, bytes, signed integers and floats are only their payload
+        3 | 4 | 8 | 15 => {
+            payload(offset)?;
+            Ok((Value::Other, offset + size))
+        }
+        5 | 6 | 9 | 10 => Ok((Value::Uint(read_uint(section, offset, size)?), offset + size)),
+        7 => {
+            let mut entries = Vec::with_capacity(size);
+            for _ in 0..size {
+                let (key, next) = decode_nested(section, offset, depth + 1)?;
+                let (value, next) = decode_nested(section, next, depth + 1)?;
+                match key {
+                    Value::String(key) => entries.push((key, value)),
+                    _ => return Err("Invalid MaxMind DB map key".into()),
+                }
+                offset = next;
+            }
+            Ok((Value::Map(entries), offset))
+        }
+        11 => {
+            for _ in 0..size {
+                offset = decode_nested(section, offset, depth + 1)?.1;
+            }
+            Ok((Value::Other, offset))
+        }
+        // A boolean keeps its value in the size
+        14 => Ok((Value::Other, offset)),
+        kind => Err(format!("Unsupported MaxMind DB data type {}", kind)),
+    }
+}
+
+// A MaxMind DB file read into memory, see https://maxmind.github.io/MaxMind-DB/
+struct Database {
+    data: Vec<u8>,
+    node_count: u32,
+    record_size: u16,
+    ip_version: u16,
+    // The node IPv4 addresses start from in an IPv6 tree, at ::/96
+    ipv4_start: u32,
+}
+
+impl Database {
+    fn open(path: &Path) -> Result<Database, String> {
+        let invalid = |reason: &str| format!("{} is not a MaxMind DB file: {}", path.display(), reason);
+        let data = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let marker = data.windows(METADATA_MARKER.len()).rposition(|w| w == METADATA_MARKER)
+            .ok_or_else(|| invalid("no metadata"))?;
+        let (metadata, _) = decode(&data[marker + METADATA_MARKER.len()..], 0).map_err(|e| invalid(&e))?;
+        let field = |key: &str| metadata.get(&[key]).and_then(Value::as_uint).ok_or_else(|| invalid(&format!("no {} in the metadata", key)));
+
+        let mut database = Database {
+            node_count: field("node_count")? as u32,
+            record_size: field("record_size")? as u16,
+            ip_version: field("ip_version")? as u16,
+            ipv4_start: 0,
+            data,
+        };
+        if ![24, 28, 32].contains(&database.record_size) {
+            return Err(invalid(&format!("unsupported record size {}", database.record_size)));
+        }
+        if database.data_section() > marker {
+            return Err(invalid("the search tree is larger than the file"));
+        }
+        if database.ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= database.node_count {
+                    break
+                }
+                node = database.record(node, 0);
+            }
+            database.ipv4_start = node;
+        }
+        Ok(database)
+    }
+
+    fn data_section(&self) -> usize {
+        self.node_count as usize * self.record_size as usize / 4 + DATA_SECTION_SEPARATOR
+    }
+
+    // The left (0) or right (1) record of a node
+    fn record(&self, node: u32, side: usize) -> u32 {
+        let base = node as usize * self.record_size as usize / 4;
+        let bytes = |offset: usize, size: usize| read_uint(&self.data, base + offset, size).unwrap_or(0) as u32;
+        match (self.record_size, side) {
+            (24, side) => bytes(side * 3, 3),
+            (28, 0) => (bytes(3, 1) & 0xF0) << 20 | bytes(0, 3),
+            (28, _) => (bytes(3, 1) & 0x0F) << 24 | bytes(4, 3),
+            (_, side) => bytes(side * 4, 4),
+        }
+    }
+
+    fn lookup(&self, ip: IpAddr) -> Result<Option<Value>, String> {
+        let (bits, mut node): (Vec<u8>, u32) = match (ip, self.ip_version) {
+            (IpAddr::V4(ip), 6) => (ip.octets().to_vec(), self.ipv4_start),
+            (IpAddr::V4(ip), _) => (ip.octets().to_vec(), 0),
+            (IpAddr::V6(ip), 6) => (ip.octets().to_vec(), 0),
+            (IpAddr::V6(_), _) => return Ok(None),
+        };
+        for bit in 0..bits.len() * 8 {
+            if node >= self.node_count {
+                break
+            }
+            node = self.record(node, (bits[bit / 8] >> (7 - bit % 8)) as usize & 1);
+        }
+        match node.cmp(&self.node_count) {
+            std::cmp::Ordering::Greater => {
+                let offset = ((node - self.node_count) as usize).checked_sub(DATA_SECTION_SEPARATOR)
+                    .ok_or("Invalid MaxMind DB record")?;
+                Ok(Some(decode(&self.data[self.data_section()..], offset)?.0))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// Looks up source IPs in one or more databases, like a country and an ASN one, taking each
+// field from the first that has it. Workers share a cache of the recently seen addresses
+pub struct GeoIp {
+    databases: Vec<Database>,
+    cache: Mutex<LruCache<IpAddr, Geo>>,
+}
+
+impl GeoIp {
+    pub fn open(paths: &[PathBuf]) -> Result<GeoIp, String> {
+        Ok(GeoIp {
+            databases: paths.iter().map(|path| Database::open(path)).collect::<Result<_, _>>()?,
+            cache: Mutex::new(LruCache::new(NonZeroUsize::new(CACHE_SIZE).unwrap())),
+        })
+    }
+
+    // Addresses that don't parse, like a unix socket's, have nothing to look up. IPv4 clients of
+    // a dual-stack listener are logged as ::ffff:a.b.c.d and looked up as the IPv4 address
+    pub fn lookup(&self, ip: &str) -> Option<Geo> {
+        let ip = ip.parse::<IpAddr>().ok()?.to_canonical();
+        if let Some(geo) = self.cache.lock().unwrap().get(&ip) {
+            return Some(geo.clone());
+        }
+
+        let mut geo = Geo::default();
+        for database in &self.databases {
+            // A corrupt record is treated like an address the database doesn't know
+            if let Ok(Some(value)) = database.lookup(ip) {
+                geo.country = geo.country.or_else(|| value.get(&["country", "iso_code"]).and_then(Value::as_str).map(str::to_string));
+                geo.asn = geo.asn.or_else(|| value.get(&["autonomous_system_number"]).and_then(Value::as_uint).map(|asn| asn as u32));
+            }
+        }
+        self.cache.lock().unwrap().put(ip, geo.clone());
+        Some(geo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(names: &[&str]) -> GeoIp {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/geoip");
+        GeoIp::open(&names.iter().map(|name| fixtures.join(name)).collect::<Vec<PathBuf>>()).unwrap()
+    }
+
+    #[test]
+    fn ipv4_in_ipv6_database() {
+        for name in ["country24.mmdb", "country28.mmdb", "country32.mmdb"] {
+            let geoip = open(&[name]);
+            // Through a pointer to a country shared between records
+            assert_eq!(geoip.lookup("10.0.0.5").unwrap().country.as_deref(), Some("US"), "{}", name);
+            assert_eq!(geoip.lookup("10.0.1.9").unwrap().country.as_deref(), Some("DE"), "{}", name);
+            assert_eq!(geoip.lookup("2001:db8::1").unwrap().country.as_deref(), Some("FI"), "{}", name);
+            assert_eq!(geoip.lookup("192.0.2.1").unwrap().country, None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn asn_lookups() {
+        for name in ["asn.mmdb", "asn4.mmdb"] {
+            let geoip = open(&[name]);
+            assert_eq!(geoip.lookup("10.20.30.40").unwrap().asn, Some(64512), "{}", name);
+            assert_eq!(geoip.lookup("::ffff:10.20.30.40").unwrap().asn, Some(64512), "{}", name);
+            assert_eq!(geoip.lookup("11.0.0.1").unwrap().asn, None, "{}", name);
+        }
+        // An IPv4 database has nothing on IPv6 addresses
+        assert_eq!(open(&["asn4.mmdb"]).lookup("2001:db8::1").unwrap().asn, None);
+    }
+
+    #[test]
+    fn fields_from_several_databases() {
+        let geoip = open(&["country24.mmdb", "asn.mmdb"]);
+        let geo = geoip.lookup("10.0.1.2").unwrap();
+        assert_eq!((geo.country.as_deref(), geo.asn), (Some("DE"), Some(64512)));
+        // The second time comes from the cache
+        let geo = geoip.lookup("10.0.1.2").unwrap();
+        assert_eq!((geo.country.as_deref(), geo.asn), (Some("DE"), Some(64512)));
+    }
+
+    #[test]
+    fn unparseable_addresses() {
+        assert!(open(&["asn.mmdb"]).lookup("unix").is_none());
+    }
+
+    #[test]
+    fn not_a_database() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/geoip");
+        assert!(GeoIp::open(&[fixtures.join("mkmmdb.py")]).is_err());
+    }
+}
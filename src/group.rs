@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use serde::Serialize;
+
+use l8r::HaproxyLogEntry;
+use crate::stats::percentile;
+use crate::utils::bold;
+
+#[derive(Debug, Default)]
+pub struct GroupAgg {
+    pub count: u64,
+    pub errors: u64,
+    pub totals: Vec<u64>,
+}
+
+impl GroupAgg {
+    fn add(&mut self, entry: &HaproxyLogEntry) {
+        self.count += 1;
+        if entry.is_error() {
+            self.errors += 1;
+        }
+        self.totals.push(entry.timers.total);
+    }
+
+    fn merge(&mut self, other: GroupAgg) {
+        self.count += other.count;
+        self.errors += other.errors;
+        self.totals.extend(other.totals);
+    }
+}
+
+// The group an entry falls in, entries without the field share the `-` group
+fn key(entry: &HaproxyLogEntry, field: &str) -> String {
+    entry.field(field).unwrap_or_else(|| "-".to_string())
+}
+
+#[derive(Debug, Default)]
+pub struct Groups {
+    pub groups: HashMap<String, GroupAgg>,
+}
+
+impl Groups {
+    pub fn add(&mut self, entry: &HaproxyLogEntry, field: &str) {
+        self.groups.entry(key(entry, field)).or_default().add(entry);
+    }
+
+    pub fn merge(mut self, other: Groups) -> Groups {
+        for (group, agg) in other.groups {
+            self.groups.entry(group).or_default().merge(agg);
+        }
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupSummary {
+    pub group: String,
+    pub count: u64,
+    pub errors: u64,
+    pub mean: Option<f64>,
+    pub median: Option<u64>,
+}
+
+// Biggest groups first
+pub fn summarize(groups: Groups) -> Vec<GroupSummary> {
+    let mut summaries: Vec<GroupSummary> = groups.groups.into_iter()
+        .map(|(group, mut agg)| {
+            agg.totals.sort_unstable();
+            GroupSummary {
+                group,
+                count: agg.count,
+                errors: agg.errors,
+                mean: match agg.totals.len() {
+                    0 => None,
+                    len => Some(agg.totals.iter().sum::<u64>() as f64 / len as f64),
+                },
+                median: percentile(&agg.totals, 50.0),
+            }
+        })
+        .collect();
+    summaries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.group.cmp(&b.group)));
+    summaries
+}
+
+pub fn output_groups(summaries: &[GroupSummary], field: &str, color: bool) -> String {
+    let width = summaries.iter().map(|s| s.group.len()).chain([field.len()]).max().unwrap_or(0);
+
+    let header = format!("{:<width$} {:>10} {:>10} {:>10} {:>10}", field, "Requests", "Errors", "Mean", "Median", width = width);
+    let mut result = format!("{}\n", bold(&header, color));
+    for summary in summaries {
+        result.push_str(&format!("{:<width$} {:>10} {:>10} {:>10} {:>10}\n",
+            summary.group, summary.count, summary.errors,
+            summary.mean.map_or("-".to_string(), |m| format!("{:.1}ms", m)),
+            summary.median.map_or("-".to_string(), |m| format!("{}ms", m)),
+            width = width));
+    }
+    result
+}
+
+pub fn output_groups_json(summaries: &[GroupSummary]) -> String {
+    serde_json::to_string(summaries).unwrap()
+}
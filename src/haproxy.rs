@@ -1,38 +1,180 @@
-use colored::Colorize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use chrono::NaiveDateTime;
+use colored::{Color, Colorize};
 use serde::Serialize;
-use crate::RE;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::error::ParseError;
+
+pub static RE: Lazy<Regex> = regex_static::lazy_regex!(r#"^(?P<month>\p{L}{3,5}\.?)\s+(?P<day>\d{1,2})\s+(?P<time>[0-9:]{8})\s+(?P<host>[\w.-]+)\s+(?P<process_id>(?P<program>[\w.-]+)(?:\[(?P<pid>\d+)\])?):\s+(?P<source_ip_port>\[[0-9A-Fa-f:.]+\]:\d+|[0-9A-Fa-f:.]+:\d+)\s+\[(?P<time_stamp_accepted>.+)\]\s+(?P<frontend_name>\w+)\s+(?P<backend_name>[\w-]+)/(?P<server_name>[-\w]+)\s+(?P<queues_stats>(?:-?\d+/){3,4}\+?\d+)\s+(?P<response_code>\d+)\s+(?P<bytes_read>\+?\d+|-)\s-\s-\s(?P<termination_state>[-A-Za-z]{4})\s(?P<conn_counts>\d+/\d+/\d+/\d+/\+?\d+)\s+(?P<queue>\d+/\d+)\s+(?:\{(?P<captured_request_headers>[^}]*)\}\s+)?(?:\{(?P<captured_response_headers>[^}]*)\}\s+)?"(?P<request>.*)"$"#);
+// The default TCP log format, which has no status, cookies or request and only three timers
+pub static TCP_RE: Lazy<Regex> = regex_static::lazy_regex!(r#"^(?P<month>\p{L}{3,5}\.?)\s+(?P<day>\d{1,2})\s+(?P<time>[0-9:]{8})\s+(?P<host>[\w.-]+)\s+(?P<process_id>(?P<program>[\w.-]+)(?:\[(?P<pid>\d+)\])?):\s+(?P<source_ip_port>\[[0-9A-Fa-f:.]+\]:\d+|[0-9A-Fa-f:.]+:\d+)\s+\[(?P<time_stamp_accepted>.+)\]\s+(?P<frontend_name>\w+)\s+(?P<backend_name>[\w-]+)/(?P<server_name>[-\w]+)\s+(?P<queues_stats>-?\d+/-?\d+/\+?\d+)\s+(?P<bytes_read>\+?\d+|-)\s+(?P<termination_state>[-A-Za-z]{2})\s+(?P<conn_counts>\d+/\d+/\d+/\d+/\+?\d+)\s+(?P<queue>\d+/\d+)$"#);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Http,
+    Tcp,
+}
+
+impl LogFormat {
+    fn regex(&self) -> &'static Regex {
+        match self {
+            LogFormat::Http => &RE,
+            LogFormat::Tcp => &TCP_RE,
+        }
+    }
+}
+
+// An empty block is no headers rather than one empty one
+fn split_captured_headers(s: &str) -> Vec<&str> {
+    match s {
+        "" => vec![],
+        s => s.split('|').collect(),
+    }
+}
+
+// Named groups every log regex has to capture for an entry to be built from it
+pub const REQUIRED_GROUPS: &[&str] = &[
+    "month", "day", "time", "host", "process_id", "program", "pid", "source_ip_port", "time_stamp_accepted",
+    "frontend_name", "backend_name", "server_name", "queues_stats", "bytes_read", "termination_state", "conn_counts", "queue",
+];
+
+// The `REQUIRED_GROUPS` a custom regex doesn't have
+pub fn missing_groups(regex: &Regex) -> Vec<&'static str> {
+    REQUIRED_GROUPS.iter().copied()
+        .filter(|group| !regex.capture_names().flatten().any(|name| name == *group))
+        .collect()
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            LogFormat::Http => "http",
+            LogFormat::Tcp => "tcp",
+        })
+    }
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(LogFormat::Http),
+            "tcp" => Ok(LogFormat::Tcp),
+            _ => Err(format!("Unknown log format {}, expected http or tcp", s)),
+        }
+    }
+}
+
 
 #[derive(Debug, Serialize)]
 pub struct HaproxyTimers {
     pub raw: String,
-    pub client_request: u64,
-    pub queue_wait: u64,
-    pub establish: u64,
-    pub server_response: u64,
+    // None in TCP logs, which only have Tw/Tc/Tt, and for every phase HAProxy logged as -1
+    // because it never completed, like the connect of a request the client aborted
+    pub client_request: Option<u64>,
+    pub queue_wait: Option<u64>,
+    pub establish: Option<u64>,
+    pub server_response: Option<u64>,
     pub total: u64,
+    // With `option logasap` the total is prefixed with `+`, it's only the time until the log was emitted
+    pub total_approximate: bool,
 }
 
 impl HaproxyTimers {
-    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let parts: Vec<&str> = s.split('/').collect();
-        if parts.len() != 5 {
-            return Err("Failed to parse timers".into());
-        }
+    fn parse(s: &str) -> Result<Self, ParseError> {
+        let number = |t: &str| t.parse::<u64>().map_err(|_| ParseError::BadTimers { raw: s.to_string() });
+        let timer = |t: &str| match t {
+            "-1" => Ok(None),
+            t => number(t).map(Some),
+        };
+        let (client_request, queue_wait, establish, server_response, total) = match s.split('/').collect::<Vec<&str>>()[..] {
+            [tq, tw, tc, tr, tt] => (timer(tq)?, tw, tc, timer(tr)?, tt),
+            // Versions and formats that leave out the client request time
+            [tw, tc, tr, tt] => (None, tw, tc, timer(tr)?, tt),
+            [tw, tc, tt] => (None, tw, tc, None, tt),
+            ref timers => return Err(ParseError::TimerCount { count: timers.len(), raw: s.to_string() }),
+        };
 
         Ok(HaproxyTimers {
             raw: s.to_string(),
-            client_request: parts[0].parse()?,
-            queue_wait: parts[1].parse()?,
-            establish: parts[2].parse()?,
-            server_response: parts[3].parse()?,
-            total: parts[4].parse()?,
+            client_request,
+            queue_wait: timer(queue_wait)?,
+            establish: timer(establish)?,
+            server_response,
+            total: number(total.trim_start_matches('+'))?,
+            total_approximate: total.starts_with('+'),
+        })
+    }
+}
+
+// One of the five timers, by its field name or HAProxy's own Tq/Tw/Tc/Tr/Tt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timer {
+    ClientRequest,
+    QueueWait,
+    Establish,
+    ServerResponse,
+    Total,
+}
+
+impl std::str::FromStr for Timer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "client_request" | "tq" => Ok(Timer::ClientRequest),
+            "queue_wait" | "tw" => Ok(Timer::QueueWait),
+            "establish" | "tc" => Ok(Timer::Establish),
+            "server_response" | "tr" => Ok(Timer::ServerResponse),
+            "total" | "tt" => Ok(Timer::Total),
+            _ => Err(format!("Unknown timer {}, expected client_request, queue_wait, establish, server_response or total", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Timer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Timer::ClientRequest => "client_request",
+            Timer::QueueWait => "queue_wait",
+            Timer::Establish => "establish",
+            Timer::ServerResponse => "server_response",
+            Timer::Total => "total",
         })
     }
 }
 
+impl HaproxyTimers {
+    // Whether the format has the timer at all, even when it was logged as -1
+    pub fn logged(&self, timer: Timer) -> bool {
+        match timer {
+            Timer::ClientRequest => self.raw.split('/').count() == 5,
+            Timer::ServerResponse => self.raw.split('/').count() >= 4,
+            _ => true,
+        }
+    }
+
+    // None for the timers TCP logs don't have and those logged as -1
+    pub fn get(&self, timer: Timer) -> Option<u64> {
+        match timer {
+            Timer::ClientRequest => self.client_request,
+            Timer::QueueWait => self.queue_wait,
+            Timer::Establish => self.establish,
+            Timer::ServerResponse => self.server_response,
+            Timer::Total => Some(self.total),
+        }
+    }
+}
+
+// As logged, which keeps the -1 of phases that never completed
 impl std::fmt::Display for HaproxyTimers {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}/{}/{}/{}/{}", self.client_request, self.queue_wait, self.establish, self.server_response, self.total)
+        write!(f, "{}", self.raw)
     }
 }
 
@@ -147,18 +289,35 @@ pub struct HaproxyTerminationState {
     pub raw: String,
     pub termination_reason: HaproxyTerminationStateEntry,
     pub session_state: HaproxyTerminationStateEntry,
-    pub persistence_cookie: HaproxyTerminationStateEntry,
-    pub persistence_operations: HaproxyTerminationStateEntry,
+    // Cookies only exist in HTTP mode, TCP logs have two flags
+    pub persistence_cookie: Option<HaproxyTerminationStateEntry>,
+    pub persistence_operations: Option<HaproxyTerminationStateEntry>,
 }
 
+// The flags HAProxy documents for each position of the termination state
+pub const TERMINATION_FLAGS: [&str; 4] = ["CSPLRIDUKcs-", "RQCHDLT-", "NIDVEOU-", "NIUPRD-"];
+
 impl HaproxyTerminationState {
-    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let termination_reason = HaproxyTerminationStateEntry::reason(s.chars().nth(0).ok_or("")?);
-        let session_state = HaproxyTerminationStateEntry::state(s.chars().nth(1).ok_or("")?);
-        let persistence_cookie = HaproxyTerminationStateEntry::cookie(s.chars().nth(2).ok_or("")?);
-        let persistence_operations = HaproxyTerminationStateEntry::operations(s.chars().nth(3).ok_or("")?);
-        let raw = s.to_string();
+    // Fails on the first flag outside of its position's alphabet, a sign of a truncated or garbled line
+    pub fn check_flags(&self) -> Result<(), ParseError> {
+        match self.raw.chars().zip(TERMINATION_FLAGS).find(|(flag, flags)| !flags.contains(*flag)) {
+            Some((flag, _)) => Err(ParseError::UnknownTerminationFlag { flag, raw: self.raw.clone() }),
+            None => Ok(()),
+        }
+    }
 
+    fn parse(s: &str) -> Result<Self, ParseError> {
+        let (reason, state, cookie, operations) = match s.chars().collect::<Vec<char>>()[..] {
+            [reason, state, cookie, operations] => (reason, state, Some(cookie), Some(operations)),
+            [reason, state] => (reason, state, None, None),
+            // Two or four flags
+            _ => return Err(ParseError::BadTerminationState { raw: s.to_string() }),
+        };
+        let termination_reason = HaproxyTerminationStateEntry::reason(reason);
+        let session_state = HaproxyTerminationStateEntry::state(state);
+        let persistence_cookie = cookie.map(HaproxyTerminationStateEntry::cookie);
+        let persistence_operations = operations.map(HaproxyTerminationStateEntry::operations);
+        let raw = s.to_string();
 
         Ok(HaproxyTerminationState {
             raw,
@@ -170,13 +329,18 @@ impl HaproxyTerminationState {
     }
 
     pub fn is_error(&self) -> bool {
-        !(self.termination_reason.shorthand == '-' && self.session_state.shorthand == '-' && self.persistence_cookie.shorthand == '-' && self.persistence_operations.shorthand == '-')
+        let normal = |entry: &Option<HaproxyTerminationStateEntry>| entry.as_ref().is_none_or(|e| e.shorthand == '-');
+        !(self.termination_reason.shorthand == '-' && self.session_state.shorthand == '-' && normal(&self.persistence_cookie) && normal(&self.persistence_operations))
     }
 }
 
 impl std::fmt::Display for HaproxyTerminationState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}{}{}", self.termination_reason.shorthand, self.session_state.shorthand, self.persistence_cookie.shorthand, self.persistence_operations.shorthand)
+        write!(f, "{}{}", self.termination_reason.shorthand, self.session_state.shorthand)?;
+        for entry in [&self.persistence_cookie, &self.persistence_operations].into_iter().flatten() {
+            write!(f, "{}", entry.shorthand)?;
+        }
+        Ok(())
     }
 }
 
@@ -187,30 +351,73 @@ pub struct HaproxyConnectionCounts {
     pub limit: u64,
     pub max: u64,
     pub total: u64,
+    // HAProxy's retries counter
     pub rejected: u64,
+    // The retries were prefixed with `+`, the request ended up on another server
+    #[serde(skip)]
+    pub redispatched: bool,
 }
 
 impl HaproxyConnectionCounts {
-    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    fn parse(s: &str) -> Result<Self, ParseError> {
+        let count = |c: &str| c.parse::<u64>().map_err(|_| ParseError::BadConnCounts { raw: s.to_string() });
         let parts: Vec<&str> = s.split('/').collect();
         if parts.len() != 5 {
-            return Err("Failed to parse connection counts".into());
+            return Err(ParseError::BadConnCounts { raw: s.to_string() });
         }
 
         Ok(HaproxyConnectionCounts {
             raw: s.to_string(),
-            current: parts[0].parse()?,
-            limit: parts[1].parse()?,
-            max: parts[2].parse()?,
-            total: parts[3].parse()?,
-            rejected: parts[4].parse()?,
+            current: count(parts[0])?,
+            limit: count(parts[1])?,
+            max: count(parts[2])?,
+            total: count(parts[3])?,
+            rejected: count(parts[4].trim_start_matches('+'))?,
+            redispatched: parts[4].starts_with('+'),
         })
     }
 }
 
 impl std::fmt::Display for HaproxyConnectionCounts {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}/{}/{}/{}/{}", self.current, self.limit, self.max, self.total, self.rejected)
+        let redispatched = if self.redispatched { "+" } else { "" };
+        write!(f, "{}/{}/{}/{}/{}{}", self.current, self.limit, self.max, self.total, redispatched, self.rejected)
+    }
+}
+
+// Flattened into the entry, so the combined address keeps its `source_ip_port` key
+#[derive(Debug, Serialize)]
+pub struct HaproxySourceAddress<'a> {
+    #[serde(rename = "source_ip_port")]
+    pub raw: &'a str,
+    // Without the brackets of a bracketed IPv6 address
+    #[serde(rename = "source_ip")]
+    pub ip: &'a str,
+    #[serde(rename = "source_port")]
+    pub port: u16,
+}
+
+impl<'a> HaproxySourceAddress<'a> {
+    // `127.0.0.1:6102`, `[::1]:443` or HAProxy's own unbracketed `::1:443`
+    fn parse(s: &'a str) -> Result<Self, ParseError> {
+        let invalid = || ParseError::BadSourceAddress { raw: s.to_string() };
+        let (ip, port) = s.rsplit_once(':').ok_or_else(invalid)?;
+        let ip = match ip.strip_prefix('[') {
+            Some(ip) => ip.strip_suffix(']').ok_or_else(invalid)?,
+            None => ip,
+        };
+
+        Ok(HaproxySourceAddress {
+            raw: s,
+            ip,
+            port: port.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl std::fmt::Display for HaproxySourceAddress<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
     }
 }
 
@@ -221,15 +428,16 @@ pub struct HaproxyQueueStats {
 }
 
 impl HaproxyQueueStats {
-    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    fn parse(s: &str) -> Result<Self, ParseError> {
+        let length = |l: &str| l.parse::<u64>().map_err(|_| ParseError::BadQueue { raw: s.to_string() });
         let parts: Vec<&str> = s.split('/').collect();
         if parts.len() != 2 {
-            return Err("Failed to parse queue stats".into());
+            return Err(ParseError::BadQueue { raw: s.to_string() });
         }
 
         Ok(HaproxyQueueStats {
-            server: parts[0].parse()?,
-            backend: parts[1].parse()?,
+            server: length(parts[0])?,
+            backend: length(parts[1])?,
         })
     }
 }
@@ -240,122 +448,885 @@ impl std::fmt::Display for HaproxyQueueStats {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum StatusClass {
+    Informational,
+    Success,
+    Redirection,
+    ClientError,
+    ServerError,
+    Unknown,
+}
+
+impl StatusClass {
+    pub fn from_code(code: u16) -> StatusClass {
+        match code {
+            100..=199 => StatusClass::Informational,
+            200..=299 => StatusClass::Success,
+            300..=399 => StatusClass::Redirection,
+            400..=499 => StatusClass::ClientError,
+            500..=599 => StatusClass::ServerError,
+            _ => StatusClass::Unknown,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, StatusClass::ClientError | StatusClass::ServerError)
+    }
+}
+
+impl std::fmt::Display for StatusClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            StatusClass::Informational => "1xx",
+            StatusClass::Success => "2xx",
+            StatusClass::Redirection => "3xx",
+            StatusClass::ClientError => "4xx",
+            StatusClass::ServerError => "5xx",
+            StatusClass::Unknown => "unknown",
+        })
+    }
+}
+
+// The locale syslog formatted the month of the prefix in, HAProxy's own accept date is always English
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MonthLocale {
+    #[default]
+    C,
+    German,
+    French,
+    Spanish,
+    Italian,
+    Dutch,
+    Swedish,
+}
+
+impl MonthLocale {
+    // Abbreviations as glibc's %b prints them, lowercase and without a trailing dot
+    fn names(&self) -> [&'static str; 12] {
+        match self {
+            MonthLocale::C => ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"],
+            MonthLocale::German => ["jan", "feb", "mär", "apr", "mai", "jun", "jul", "aug", "sep", "okt", "nov", "dez"],
+            MonthLocale::French => ["janv", "févr", "mars", "avr", "mai", "juin", "juil", "août", "sept", "oct", "nov", "déc"],
+            MonthLocale::Spanish => ["ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic"],
+            MonthLocale::Italian => ["gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov", "dic"],
+            MonthLocale::Dutch => ["jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov", "dec"],
+            MonthLocale::Swedish => ["jan", "feb", "mars", "apr", "maj", "juni", "juli", "aug", "sep", "okt", "nov", "dec"],
+        }
+    }
+
+    // 1-based month number, English names are always accepted as well
+    pub fn month_number(&self, name: &str) -> Option<u32> {
+        let name = name.trim_end_matches('.').to_lowercase();
+        let position = |names: [&str; 12]| names.iter().position(|n| *n == name);
+        position(self.names())
+            .or_else(|| position(MonthLocale::C.names()))
+            .map(|i| i as u32 + 1)
+    }
+}
+
+impl std::str::FromStr for MonthLocale {
+    type Err = String;
+
+    // Takes either a language code or a full locale name like de_DE.UTF-8
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['_', '.', '@']).next().unwrap_or(s).to_lowercase();
+        match language.as_str() {
+            "c" | "posix" | "en" => Ok(MonthLocale::C),
+            "de" => Ok(MonthLocale::German),
+            "fr" => Ok(MonthLocale::French),
+            "es" => Ok(MonthLocale::Spanish),
+            "it" => Ok(MonthLocale::Italian),
+            "nl" => Ok(MonthLocale::Dutch),
+            "sv" => Ok(MonthLocale::Swedish),
+            _ => Err(format!("Unsupported locale {}, expected one of: C, en, de, fr, es, it, nl, sv", s)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HaproxyRequest<'a> {
+    // As logged, unless the query string has been stripped
+    pub raw: Cow<'a, str>,
+    pub method: Option<&'a str>,
+    // Without the query string, which is kept apart in `query`
+    pub path: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<&'a str>,
+    pub protocol: Option<&'a str>,
+}
+
+impl<'a> HaproxyRequest<'a> {
+    // Anything not shaped like `METHOD TARGET [PROTOCOL]`, such as `<BADREQ>`, only keeps the raw text
+    fn parse(s: &'a str) -> Self {
+        let unparsed = HaproxyRequest { raw: Cow::Borrowed(s), method: None, path: None, query: None, protocol: None };
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let (method, target, protocol) = match parts[..] {
+            [method, target] => (method, target, None),
+            [method, target, protocol] if protocol.starts_with("HTTP/") => (method, target, Some(protocol)),
+            _ => return unparsed,
+        };
+        if !method.chars().all(|c| c.is_ascii_uppercase() || c == '-' || c == '_') {
+            return unparsed
+        }
+
+        // Everything after the first `?` is the query, any later `?` are part of it
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+        HaproxyRequest { raw: Cow::Borrowed(s), method: Some(method), path: Some(Cow::Borrowed(path)), query, protocol }
+    }
+
+    fn normalize_path(&mut self) {
+        if let Some(Cow::Owned(path)) = self.path.as_deref().map(normalize_path) {
+            self.path = Some(Cow::Owned(path));
+        }
+    }
+
+    fn strip_query(&mut self) {
+        if let Some(query) = self.query.take() {
+            self.raw = Cow::Owned(self.raw.replacen(&format!("?{}", query), "", 1));
+        }
+    }
+}
+
+static UUID_SEGMENT: Lazy<Regex> = regex_static::lazy_regex!(r"^[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}$");
+static NUMERIC_SEGMENT: Lazy<Regex> = regex_static::lazy_regex!(r"^\d+$");
+
+// Segments matching a rule are replaced by its placeholder, the first matching rule wins
+static PATH_RULES: [(&Lazy<Regex>, &str); 2] = [(&UUID_SEGMENT, ":uuid"), (&NUMERIC_SEGMENT, ":id")];
+
+// Replaces IDs in a path with placeholders, like `/users/12345/profile` with `/users/:id/profile`,
+// so paths that only differ in them count as one
+pub fn normalize_path(path: &str) -> Cow<'_, str> {
+    let placeholder = |segment: &str| PATH_RULES.iter().find(|(rule, _)| rule.is_match(segment)).map(|(_, placeholder)| *placeholder);
+    if !path.split('/').any(|segment| placeholder(segment).is_some()) {
+        return Cow::Borrowed(path)
+    }
+    Cow::Owned(path.split('/').map(|segment| placeholder(segment).unwrap_or(segment)).collect::<Vec<&str>>().join("/"))
+}
+
+impl std::fmt::Display for HaproxyRequest<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+// FNV-1a, stable across runs and Rust versions unlike the std hashers
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+// The xterm 256-color cube indices bright enough to read on a dark background, without the greys
+static NAME_PALETTE: [u8; 186] = name_palette();
+
+const fn name_palette() -> [u8; 186] {
+    let mut palette = [0; 186];
+    let (mut cube, mut len) = (0, 0);
+    while cube < 216 {
+        let (r, g, b) = (cube / 36, cube / 6 % 6, cube % 6);
+        let brightest = if r > g { r } else { g };
+        if (brightest >= 3 || b >= 3) && !(r == g && g == b) {
+            palette[len] = 16 + cube as u8;
+            len += 1;
+        }
+        cube += 1;
+    }
+    palette
+}
+
+// The ANSI-256 color index a name gets, the same one every time
+pub fn name_color(name: &str) -> u8 {
+    NAME_PALETTE[(fnv1a(name) % NAME_PALETTE.len() as u64) as usize]
+}
+
+// `colored` only knows the 16 basic colors and RGB, so the indexed ones are written out by hand
+fn paint_indexed(s: &str, index: u8) -> String {
+    match colored::control::SHOULD_COLORIZE.should_colorize() {
+        true => format!("\x1b[38;5;{}m{}\x1b[0m", index, s),
+        false => s.to_string(),
+    }
+}
+
+// The colors of the colorized output, the defaults are meant for a dark background
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    // Everything without a color of its own
+    pub text: Color,
+    pub frontend: Color,
+    pub backend: Color,
+    pub server: Color,
+    pub informational: Color,
+    pub success: Color,
+    pub redirection: Color,
+    pub client_error: Color,
+    pub server_error: Color,
+    pub termination_normal: Color,
+    pub termination_error: Color,
+    pub retried: Color,
+    pub redispatched: Color,
+    // HEAD and OPTIONS share the GET color and PATCH the PUT one
+    pub method_get: Color,
+    pub method_post: Color,
+    pub method_put: Color,
+    pub method_delete: Color,
+    pub method_other: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            text: Color::White,
+            frontend: Color::Magenta,
+            backend: Color::Yellow,
+            server: Color::Blue,
+            informational: Color::White,
+            success: Color::Green,
+            redirection: Color::Yellow,
+            client_error: Color::Red,
+            server_error: Color::Red,
+            termination_normal: Color::Green,
+            termination_error: Color::Red,
+            retried: Color::Yellow,
+            redispatched: Color::Red,
+            method_get: Color::Green,
+            method_post: Color::Blue,
+            method_put: Color::Cyan,
+            method_delete: Color::Red,
+            method_other: Color::White,
+        }
+    }
+}
+
+impl Theme {
+    // Every formatter colors status codes through here so they can't drift apart
+    pub fn status(&self, class: StatusClass) -> Color {
+        match class {
+            StatusClass::Informational => self.informational,
+            StatusClass::Success => self.success,
+            StatusClass::Redirection => self.redirection,
+            StatusClass::ClientError => self.client_error,
+            StatusClass::ServerError => self.server_error,
+            StatusClass::Unknown => self.text,
+        }
+    }
+
+    pub fn method(&self, method: &str) -> Color {
+        match method {
+            "GET" | "HEAD" | "OPTIONS" => self.method_get,
+            "POST" => self.method_post,
+            "PUT" | "PATCH" => self.method_put,
+            "DELETE" => self.method_delete,
+            _ => self.method_other,
+        }
+    }
+
+    pub fn termination(&self, state: &HaproxyTerminationState) -> Color {
+        match state.is_error() {
+            true => self.termination_error,
+            false => self.termination_normal,
+        }
+    }
+}
+
+// Names accepted by `HaproxyLogEntry::field`, nested values are addressed with a dot
+pub const FIELD_NAMES: &[&str] = &[
+    "month", "day", "time", "host", "process_id", "program", "pid", "source_ip_port", "source_ip", "source_port", "time_stamp_accepted", "accepted_at",
+    "frontend_name", "backend_name", "server_name",
+    "timers", "timers.client_request", "timers.queue_wait", "timers.establish", "timers.server_response", "timers.total",
+    "response_code", "status_class", "bytes_read",
+    "termination_state", "termination_state.termination_reason", "termination_state.session_state",
+    "termination_state.persistence_cookie", "termination_state.persistence_operations",
+    "conn_counts", "conn_counts.current", "conn_counts.limit", "conn_counts.max", "conn_counts.total", "conn_counts.rejected",
+    "retries", "retried", "redispatched",
+    "queue", "queue.server", "queue.backend",
+    "captured_request_headers", "captured_response_headers",
+    "format", "request", "request.method", "request.path", "request.query", "request.protocol",
+];
+
+pub const FIELD_ALIASES: &[(&str, &str)] = &[
+    ("frontend", "frontend_name"),
+    ("backend", "backend_name"),
+    ("server", "server_name"),
+    ("status", "status_class"),
+    ("method", "request.method"),
+    ("path", "request.path"),
+    ("query", "request.query"),
+    ("protocol", "request.protocol"),
+    ("process_name", "program"),
+];
+
+pub fn canonical_field_name(name: &str) -> Option<&'static str> {
+    let name = FIELD_ALIASES.iter().find(|(alias, _)| *alias == name).map_or(name, |(_, field)| field);
+    FIELD_NAMES.iter().copied().find(|field| *field == name)
+}
+
+// ISO 8601 without a zone, HAProxy logs the accept date in its local time and doesn't say which
+fn format_accepted_at(t: NaiveDateTime) -> String {
+    t.format("%Y-%m-%dT%H:%M:%S%.3f").to_string()
+}
+
+fn serialize_accepted_at<S: serde::Serializer>(accepted_at: &Option<NaiveDateTime>, serializer: S) -> Result<S::Ok, S::Error> {
+    match accepted_at {
+        Some(t) => serializer.serialize_str(&format_accepted_at(*t)),
+        None => serializer.serialize_none(),
+    }
+}
+
 // May  8 00:08:30 applb05 haproxy[3091252]: 127.0.0.1:6102 [08/May/2024:00:08:30.660] mclbfe silo-mclb-silo-backend/kube-prod2-node16 0/0/9/17/26 200 1005 - - ---- 823/541/29/2/0 0/0 "GET /silo/collections/1b629de5_1aaf_47d7_8b6d_5cfdcc8337e3 HTTP/1.1"
 #[derive(Debug, Serialize)]
 pub struct HaproxyLogEntry<'a> {
+    // The unmodified line the entry was parsed from
+    #[serde(skip)]
+    pub raw_line: &'a str,
+    pub format: LogFormat,
     pub month: &'a str,
     pub day: &'a str,
     pub time: &'a str,
     pub host: &'a str,
     pub process_id: &'a str,
-    pub source_ip_port: &'a str,
+    pub program: &'a str,
+    // None when syslog logged the program without a pid, like `haproxy:`
+    pub pid: Option<u64>,
+    #[serde(flatten)]
+    pub source: HaproxySourceAddress<'a>,
     pub time_stamp_accepted: &'a str,
+    // None when the accept date doesn't parse, the fraction is kept down to the millisecond
+    #[serde(serialize_with = "serialize_accepted_at")]
+    pub accepted_at: Option<NaiveDateTime>,
     pub frontend_name: &'a str,
     pub backend_name: &'a str,
     pub server_name: &'a str,
     pub timers: HaproxyTimers,
-    pub response_code: &'a str,
-    pub bytes_read: &'a str,
+    // The HTTP-only fields are None for TCP entries
+    #[serde(skip)]
+    pub response_code_raw: Option<&'a str>,
+    pub response_code: Option<u16>,
+    #[serde(skip)]
+    pub bytes_read_raw: &'a str,
+    // None when HAProxy logged `-` instead of a byte count
+    pub bytes_read: Option<u64>,
+    // Logged with a `+` because the transfer hadn't finished yet, the count is a lower bound
+    pub bytes_read_incomplete: bool,
     pub termination_state: HaproxyTerminationState,
     pub conn_counts: HaproxyConnectionCounts,
+    // The retries counter from `conn_counts`, a custom regex can capture it as `retries` instead.
+    // None when the count doesn't fit
+    pub retries: Option<u32>,
+    // HAProxy had to retry the connection to a server at least once
+    pub retried: bool,
+    // A retry gave up on the original server and went to another one
+    pub redispatched: bool,
     pub queue: HaproxyQueueStats,
-    pub request: &'a str,
+    // The `capture request header` and `capture response header` values, in configuration order.
+    // A single block is taken as the request headers, HAProxy logs it the same way either way
+    pub captured_request_headers: Option<Vec<&'a str>>,
+    pub captured_response_headers: Option<Vec<&'a str>>,
+    pub request: Option<HaproxyRequest<'a>>,
+    // Only filled in by `parse_query`, repeated keys keep every value
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_params: Option<BTreeMap<&'a str, Vec<&'a str>>>,
 }
 
 impl<'a> HaproxyLogEntry<'a> {
-    pub fn parse(s: &'a str) -> Result<Self, Box<dyn std::error::Error>> {
-        let captures = RE.captures(s).ok_or("Failed to parse line")?;
+    // Tries the HTTP format first and falls back to TCP, unless the line was HTTP with a bad field
+    pub fn parse(s: &'a str) -> Result<Self, ParseError> {
+        match HaproxyLogEntry::parse_as(s, LogFormat::Http) {
+            Err(ParseError::RegexNoMatch) => HaproxyLogEntry::parse_as(s, LogFormat::Tcp),
+            result => result,
+        }
+    }
+
+    pub fn parse_as(s: &'a str, format: LogFormat) -> Result<Self, ParseError> {
+        HaproxyLogEntry::parse_with(s, format.regex())
+    }
+
+    // A regex of its own for a custom log-format, with at least the `REQUIRED_GROUPS`.
+    // With a response_code group the entries are HTTP ones, without it TCP ones
+    pub fn parse_with(s: &'a str, regex: &Regex) -> Result<Self, ParseError> {
+        let captures = regex.captures(s).ok_or(ParseError::RegexNoMatch)?;
+        let format = match regex.capture_names().flatten().any(|name| name == "response_code") {
+            true => LogFormat::Http,
+            false => LogFormat::Tcp,
+        };
+        // Every group but the HTTP-only ones is part of both formats
+        let field = |name: &str| captures.name(name).map(|m| m.as_str()).ok_or(ParseError::RegexNoMatch);
+        let conn_counts = HaproxyConnectionCounts::parse(field("conn_counts")?)?;
+        let retries = match captures.name("retries") {
+            Some(retries) => Some(retries.as_str().parse().map_err(|_| ParseError::BadNumber { field: "retries", raw: retries.as_str().to_string() })?),
+            None => u32::try_from(conn_counts.rejected).ok(),
+        };
+        let (retried, redispatched) = (retries != Some(0), conn_counts.redispatched);
         let data = HaproxyLogEntry {
-            month: captures.name("month").ok_or("")?.as_str(),
-            day: captures.name("day").ok_or("")?.as_str(),
-            time: captures.name("time").ok_or("")?.as_str(),
-            host: captures.name("host").ok_or("")?.as_str(),
-            process_id: captures.name("process_id").ok_or("")?.as_str(),
-            source_ip_port: captures.name("source_ip_port").ok_or("")?.as_str(),
-            time_stamp_accepted: captures.name("time_stamp_accepted").ok_or("")?.as_str(),
-            frontend_name: captures.name("frontend_name").ok_or("")?.as_str(),
-            backend_name: captures.name("backend_name").ok_or("")?.as_str(),
-            server_name: captures.name("server_name").ok_or("")?.as_str(),
-            timers: HaproxyTimers::parse(captures.name("queues_stats").ok_or("")?.as_str())?,
-            response_code: captures.name("response_code").ok_or("")?.as_str(),
-            bytes_read: captures.name("bytes_read").ok_or("")?.as_str(),
-            termination_state: HaproxyTerminationState::parse(captures.name("termination_state").ok_or("")?.as_str())?,
-            conn_counts: HaproxyConnectionCounts::parse(captures.name("conn_counts").ok_or("")?.as_str())?,
-            queue: HaproxyQueueStats::parse(captures.name("queue").ok_or("")?.as_str())?,
-            request: captures.name("request").ok_or("")?.as_str(),
+            raw_line: s,
+            format,
+            month: field("month")?,
+            day: field("day")?,
+            time: field("time")?,
+            host: field("host")?,
+            process_id: field("process_id")?,
+            program: field("program")?,
+            pid: captures.name("pid")
+                .map(|pid| pid.as_str().parse().map_err(|_| ParseError::BadNumber { field: "pid", raw: pid.as_str().to_string() }))
+                .transpose()?,
+            source: HaproxySourceAddress::parse(field("source_ip_port")?)?,
+            time_stamp_accepted: field("time_stamp_accepted")?,
+            accepted_at: NaiveDateTime::parse_from_str(field("time_stamp_accepted")?, "%d/%b/%Y:%H:%M:%S%.f").ok(),
+            frontend_name: field("frontend_name")?,
+            backend_name: field("backend_name")?,
+            server_name: field("server_name")?,
+            timers: HaproxyTimers::parse(field("queues_stats")?)?,
+            response_code_raw: captures.name("response_code").map(|code| code.as_str()),
+            response_code: captures.name("response_code")
+                .map(|code| code.as_str().parse().map_err(|_| ParseError::BadNumber { field: "response_code", raw: code.as_str().to_string() }))
+                .transpose()?,
+            bytes_read_raw: field("bytes_read")?,
+            bytes_read: match field("bytes_read")? {
+                "-" => None,
+                bytes => Some(bytes.trim_start_matches('+').parse().map_err(|_| ParseError::BadNumber { field: "bytes_read", raw: bytes.to_string() })?)
+            },
+            bytes_read_incomplete: field("bytes_read")?.starts_with('+'),
+            termination_state: HaproxyTerminationState::parse(field("termination_state")?)?,
+            conn_counts,
+            retries,
+            retried,
+            redispatched,
+            queue: HaproxyQueueStats::parse(field("queue")?)?,
+            captured_request_headers: captures.name("captured_request_headers").map(|headers| split_captured_headers(headers.as_str())),
+            captured_response_headers: captures.name("captured_response_headers").map(|headers| split_captured_headers(headers.as_str())),
+            request: captures.name("request").map(|request| HaproxyRequest::parse(request.as_str())),
+            query_params: None,
         };
 
         Ok(data)
     }
 
     pub fn colorless(&self) -> String {
-        format!("{} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {}",
-            self.month,
-            self.day,
-            self.time,
-            self.host,
-            self.process_id,
-            self.source_ip_port,
-            self.time_stamp_accepted,
-            self.frontend_name,
-            self.backend_name,
-            self.server_name,
-            self.timers,
-            self.response_code,
-            self.bytes_read,
-            self.termination_state,
-            self.conn_counts,
-            self.queue,
-            self.request
-        )
+        self.colorless_with(" ")
+    }
+
+    // The fields of `colorless` joined with something that can't be mistaken for a space inside one
+    pub fn colorless_with(&self, separator: &str) -> String {
+        let mut parts = vec![
+            self.month.to_string(),
+            self.day.to_string(),
+            self.time.to_string(),
+            self.host.to_string(),
+            self.process_id.to_string(),
+            self.source.to_string(),
+            self.time_stamp_accepted.to_string(),
+            self.frontend_name.to_string(),
+            self.backend_name.to_string(),
+            self.server_name.to_string(),
+            self.timers.to_string(),
+        ];
+        parts.extend(self.response_code_raw.map(|code| code.to_string()));
+        parts.extend([
+            self.bytes_read_raw.to_string(),
+            self.termination_state.to_string(),
+            self.conn_counts.to_string(),
+            self.queue.to_string(),
+        ]);
+        parts.extend(self.captured_headers());
+        parts.extend(self.request.as_ref().map(|request| request.to_string()));
+        parts.join(separator)
     }
+
     pub fn colorize(&self) -> String {
-        format!("{} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {}",
-            self.month.white(),
-            self.day.white(),
-            self.time.white(),
-            self.host.white(),
-            self.process_id.white(),
-            self.source_ip_port.white(),
-            self.time_stamp_accepted.white(),
-            self.frontend_name.purple(),
-            self.backend_name.yellow(),
-            self.server_name.blue(),
-            self.timers.to_string().white(),
-            match self.response_code.parse::<u16>() {
-                Ok(code) => {
-                    if code >= 200 && code < 300 {
-                        self.response_code.green()
-                    } else if code >= 300 && code < 400 {
-                        self.response_code.yellow()
-                    } else if code >= 400 {
-                        self.response_code.red()
-                    } else {
-                        self.response_code.white()
-                    }
-                }
-                Err(_) => self.response_code.white()
-            },
-            self.bytes_read.white(),
-            match self.termination_state.is_error() {
-                false => self.termination_state.to_string().green(),
-                true => self.termination_state.to_string().red()
+        self.colorize_themed(&Theme::default(), false)
+    }
+
+    // Colors backends and servers by their name, so each one keeps its own color
+    pub fn colorize_by_name(&self) -> String {
+        self.colorize_themed(&Theme::default(), true)
+    }
+
+    pub fn colorize_themed(&self, theme: &Theme, by_name: bool) -> String {
+        let named = |name: &str, color: Color| match by_name {
+            true => paint_indexed(name, name_color(name)),
+            false => name.color(color).to_string(),
+        };
+        let leading = [
+            self.month.color(theme.text),
+            self.day.color(theme.text),
+            self.time.color(theme.text),
+            self.host.color(theme.text),
+            self.process_id.color(theme.text),
+            self.source.raw.color(theme.text),
+            self.time_stamp_accepted.color(theme.text),
+            self.frontend_name.color(theme.frontend),
+        ];
+        // Painted apart from the rest, `paint_indexed` gives a plain string rather than a ColoredString
+        let names = [named(self.backend_name, theme.backend), named(self.server_name, theme.server)];
+        let mut parts = vec![self.timers.to_string().color(theme.text)];
+        parts.extend(self.response_code_raw.map(|code| code.color(theme.status(self.status_class()))));
+        parts.extend([
+            self.bytes_read_raw.color(theme.text),
+            self.termination_state.to_string().color(theme.termination(&self.termination_state)),
+            match (self.redispatched, self.retried) {
+                (true, _) => self.conn_counts.to_string().color(theme.redispatched).bold(),
+                (false, true) => self.conn_counts.to_string().color(theme.retried),
+                (false, false) => self.conn_counts.to_string().color(theme.text),
             },
-            self.conn_counts.to_string().white(),
-            self.queue.to_string().white(),
-            self.request.white()
-        )
+            self.queue.to_string().color(theme.text),
+        ]);
+        parts.extend(self.captured_headers().into_iter().map(|headers| headers.color(theme.text)));
+        let mut parts: Vec<String> = leading.iter().map(|part| part.to_string()).chain(names)
+            .chain(parts.iter().map(|part| part.to_string()))
+            .collect();
+        // Only the method of a well-formed request gets a color of its own, the rest stays neutral
+        parts.extend(self.request.as_ref().map(|request| match request.method.and_then(|method| Some((method, request.raw.strip_prefix(method)?))) {
+            Some((method, rest)) => format!("{}{}", method.color(theme.method(method)), rest.color(theme.text)),
+            None => request.raw.as_ref().color(theme.text).to_string(),
+        }));
+        parts.join(" ")
+    }
+
+    // The captured header blocks as HAProxy logs them, like `{example.com|curl/8.0}`
+    fn captured_headers(&self) -> Vec<String> {
+        [&self.captured_request_headers, &self.captured_response_headers].into_iter()
+            .flatten()
+            .map(|headers| format!("{{{}}}", headers.join("|")))
+            .collect()
+    }
+
+    // Looks up a field by its serialized name or one of the `FIELD_ALIASES`
+    pub fn field(&self, name: &str) -> Option<String> {
+        let value = match canonical_field_name(name)? {
+            "month" => self.month.to_string(),
+            "day" => self.day.to_string(),
+            "time" => self.time.to_string(),
+            "host" => self.host.to_string(),
+            "process_id" => self.process_id.to_string(),
+            "program" => self.program.to_string(),
+            "pid" => self.pid.map_or("".to_string(), |pid| pid.to_string()),
+            "source_ip_port" => self.source.to_string(),
+            "source_ip" => self.source.ip.to_string(),
+            "source_port" => self.source.port.to_string(),
+            "time_stamp_accepted" => self.time_stamp_accepted.to_string(),
+            "accepted_at" => self.accepted_at.map_or("".to_string(), format_accepted_at),
+            "frontend_name" => self.frontend_name.to_string(),
+            "backend_name" => self.backend_name.to_string(),
+            "server_name" => self.server_name.to_string(),
+            "timers" => self.timers.to_string(),
+            "timers.client_request" => self.timers.client_request.map_or("".to_string(), |t| t.to_string()),
+            "timers.queue_wait" => self.timers.queue_wait.map_or("".to_string(), |t| t.to_string()),
+            "timers.establish" => self.timers.establish.map_or("".to_string(), |t| t.to_string()),
+            "timers.server_response" => self.timers.server_response.map_or("".to_string(), |t| t.to_string()),
+            "timers.total" => self.timers.total.to_string(),
+            "response_code" => self.response_code_raw.unwrap_or("").to_string(),
+            "status_class" => self.status_class().to_string(),
+            "bytes_read" => self.bytes_read_raw.to_string(),
+            "termination_state" => self.termination_state.to_string(),
+            "termination_state.termination_reason" => self.termination_state.termination_reason.shorthand.to_string(),
+            "termination_state.session_state" => self.termination_state.session_state.shorthand.to_string(),
+            "termination_state.persistence_cookie" => self.termination_state.persistence_cookie.as_ref().map_or("".to_string(), |e| e.shorthand.to_string()),
+            "termination_state.persistence_operations" => self.termination_state.persistence_operations.as_ref().map_or("".to_string(), |e| e.shorthand.to_string()),
+            "conn_counts" => self.conn_counts.to_string(),
+            "conn_counts.current" => self.conn_counts.current.to_string(),
+            "conn_counts.limit" => self.conn_counts.limit.to_string(),
+            "conn_counts.max" => self.conn_counts.max.to_string(),
+            "conn_counts.total" => self.conn_counts.total.to_string(),
+            "conn_counts.rejected" => self.conn_counts.rejected.to_string(),
+            "retries" => self.retries.map_or("".to_string(), |retries| retries.to_string()),
+            "retried" => self.retried.to_string(),
+            "redispatched" => self.redispatched.to_string(),
+            "queue" => self.queue.to_string(),
+            "queue.server" => self.queue.server.to_string(),
+            "queue.backend" => self.queue.backend.to_string(),
+            "format" => self.format.to_string(),
+            "captured_request_headers" => self.captured_request_headers.as_ref().map_or("".to_string(), |h| h.join("|")),
+            "captured_response_headers" => self.captured_response_headers.as_ref().map_or("".to_string(), |h| h.join("|")),
+            "request" => self.request.as_ref().map_or("".to_string(), |r| r.to_string()),
+            "request.method" => self.request.as_ref().and_then(|r| r.method).unwrap_or("").to_string(),
+            "request.path" => self.path().unwrap_or("").to_string(),
+            "request.query" => self.query().unwrap_or("").to_string(),
+            "request.protocol" => self.request.as_ref().and_then(|r| r.protocol).unwrap_or("").to_string(),
+            _ => return None
+        };
+        Some(value)
+    }
+
+    // The request as it's printed, empty for TCP entries
+    pub fn request_text(&self) -> &str {
+        self.request.as_ref().map_or("", |request| request.raw.as_ref())
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.request.as_ref()?.path.as_deref()
+    }
+
+    pub fn query(&self) -> Option<&'a str> {
+        self.request.as_ref()?.query
+    }
+
+    // Values are kept as logged, without percent-decoding
+    pub fn parse_query(&mut self) {
+        let mut params: BTreeMap<&'a str, Vec<&'a str>> = BTreeMap::new();
+        for pair in self.query().unwrap_or("").split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            params.entry(key).or_default().push(value);
+        }
+        self.query_params = Some(params);
+    }
+
+    // Drops the query string from the request, which often carries tokens or personal data
+    pub fn strip_query(&mut self) {
+        if let Some(request) = &mut self.request {
+            request.strip_query();
+        }
+    }
+
+    // Only the path, the raw request keeps the IDs
+    pub fn normalize_path(&mut self) {
+        if let Some(request) = &mut self.request {
+            request.normalize_path();
+        }
+    }
+
+    pub fn month_number(&self, locale: MonthLocale) -> Option<u32> {
+        locale.month_number(self.month)
+    }
 
+    // TCP entries have no status and are always `Unknown`
+    pub fn status_class(&self) -> StatusClass {
+        self.response_code.map_or(StatusClass::Unknown, StatusClass::from_code)
     }
 
     // Check if error code is 400 or higher, or if no ---- termination_state
     pub fn is_error(&self) -> bool {
-        match self.response_code.parse::<u16>() {
-            Ok(code) => code >= 400 || self.termination_state.is_error(),
-            Err(_) => true
+        self.response_code.is_some_and(|code| code >= 400) || self.termination_state.is_error()
+    }
+
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Shared with the binary's tests, the file has no newline at the end so it can be used as it is
+    const LINE: &str = include_str!("../tests/fixtures/httplog.log");
+
+    #[test]
+    fn request_with_embedded_quotes() {
+        let line = LINE.replace("/index.html", r#"/search?q="x"&r=\"y\""#);
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert_eq!(entry.request.unwrap().raw, r#"GET /search?q="x"&r=\"y\" HTTP/1.1"#);
+        assert_eq!(entry.response_code, Some(200));
+        assert_eq!(entry.bytes_read, Some(1005));
+    }
+
+    #[test]
+    fn empty_request() {
+        let line = LINE.replace(r#""GET /index.html HTTP/1.1""#, r#""""#);
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert_eq!(entry.request.unwrap().raw, "");
+    }
+
+    #[test]
+    fn tcp_line() {
+        let line = "May  8 00:08:30 applb05 haproxy[3091252]: 127.0.0.1:6102 [08/May/2024:00:08:30.660] mclbfe silo-backend/node16 0/9/26 1005 -- 823/541/29/2/0 0/0";
+        let entry = HaproxyLogEntry::parse(line).unwrap();
+        assert_eq!(entry.format, LogFormat::Tcp);
+        assert_eq!((entry.backend_name, entry.server_name, entry.bytes_read), ("silo-backend", "node16", Some(1005)));
+        assert_eq!((entry.timers.queue_wait, entry.timers.establish, entry.timers.total), (Some(0), Some(9), 26));
+        assert_eq!((entry.response_code, entry.request.is_none()), (None, true));
+        assert_eq!(entry.termination_state.to_string(), "--");
+    }
+
+    #[test]
+    fn request_parts() {
+        let line = LINE.replace("/index.html", "/search?q=a?b&page=2");
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        let request = entry.request.unwrap();
+        assert_eq!((request.method, request.path.as_deref(), request.query, request.protocol),
+            (Some("GET"), Some("/search"), Some("q=a?b&page=2"), Some("HTTP/1.1")));
+    }
+
+    #[test]
+    fn request_without_protocol() {
+        let request = HaproxyRequest::parse("GET /index.html");
+        assert_eq!((request.method, request.path.as_deref(), request.protocol), (Some("GET"), Some("/index.html"), None));
+    }
+
+    #[test]
+    fn bad_requests_keep_only_the_raw_text() {
+        for raw in ["<BADREQ>", "get /index.html HTTP/1.1", "GET /index.html FTP/1.0", "GET / HTTP/1.1 extra"] {
+            let request = HaproxyRequest::parse(raw);
+            assert_eq!((request.raw.as_ref(), request.method, request.path, request.protocol), (raw, None, None, None), "{}", raw);
         }
-    
     }
 
+    #[test]
+    fn strip_query() {
+        let line = LINE.replace("/index.html", "/login?token=secret");
+        let mut entry = HaproxyLogEntry::parse(&line).unwrap();
+        entry.strip_query();
+        let request = entry.request.unwrap();
+        assert_eq!((request.raw.as_ref(), request.query), ("GET /login HTTP/1.1", None));
+        assert_eq!(request.path.as_deref(), Some("/login"));
+    }
+
+    #[test]
+    fn fqdn_host() {
+        let line = LINE.replace("applb05", "lb-01.example.com");
+        assert_eq!(HaproxyLogEntry::parse(&line).unwrap().host, "lb-01.example.com");
+    }
+
+    #[test]
+    fn program_and_pid() {
+        let line = LINE.replace("haproxy[3091252]", "haproxy[1234]");
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert_eq!((entry.process_id, entry.program, entry.pid), ("haproxy[1234]", "haproxy", Some(1234)));
+    }
+
+    #[test]
+    fn program_with_dashes_and_dots() {
+        let line = LINE.replace("haproxy[3091252]", "ha.proxy-prod[42]");
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert_eq!((entry.program, entry.pid), ("ha.proxy-prod", Some(42)));
+    }
+
+    #[test]
+    fn program_without_pid() {
+        let line = LINE.replace("haproxy[3091252]", "haproxy");
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert_eq!((entry.process_id, entry.program, entry.pid), ("haproxy", "haproxy", None));
+    }
+
+    #[test]
+    fn incomplete_bytes_read() {
+        let line = LINE.replace(" 1005 ", " +1234 ");
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert_eq!((entry.bytes_read, entry.bytes_read_raw, entry.bytes_read_incomplete), (Some(1234), "+1234", true));
+    }
+
+    #[test]
+    fn approximate_total_and_missing_phase() {
+        let line = LINE.replace("0/0/9/17/26", "0/0/-1/-1/+26");
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert_eq!((entry.timers.total, entry.timers.total_approximate), (26, true));
+        assert_eq!((entry.timers.establish, entry.timers.server_response), (None, None));
+        assert_eq!(entry.timers.queue_wait, Some(0));
+    }
 
+    #[test]
+    fn exact_total() {
+        let entry = HaproxyLogEntry::parse(LINE).unwrap();
+        assert_eq!((entry.timers.total, entry.timers.total_approximate, entry.bytes_read_incomplete), (26, false, false));
+    }
+
+    #[test]
+    fn four_timers() {
+        let line = LINE.replace("0/0/9/17/26", "0/9/17/26");
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert_eq!(entry.timers.client_request, None);
+        assert_eq!((entry.timers.queue_wait, entry.timers.establish, entry.timers.server_response), (Some(0), Some(9), Some(17)));
+        assert_eq!(entry.timers.total, 26);
+    }
+
+    #[test]
+    fn three_timers() {
+        let timers = HaproxyTimers::parse("0/9/26").unwrap();
+        assert_eq!((timers.client_request, timers.server_response), (None, None));
+        assert_eq!((timers.queue_wait, timers.establish, timers.total), (Some(0), Some(9), 26));
+    }
+
+    #[test]
+    fn timer_count_error() {
+        for (timers, count) in [("9/26", 2), ("0/0/0/9/17/26", 6)] {
+            let error = HaproxyTimers::parse(timers).unwrap_err();
+            assert_eq!(error.to_string(), format!("Expected 3, 4 or 5 timers, found {}: {}", count, timers));
+        }
+    }
+
+    #[test]
+    fn retries() {
+        let entry = HaproxyLogEntry::parse(LINE).unwrap();
+        assert_eq!((entry.retries, entry.retried), (Some(0), false));
+
+        let line = LINE.replace("823/541/29/2/0", "823/541/29/2/+3");
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert_eq!((entry.retries, entry.retried, entry.redispatched), (Some(3), true, true));
+        assert_eq!(serde_json::to_value(&entry).unwrap()["retries"], 3);
+        assert_eq!(entry.field(canonical_field_name("retries").unwrap()).unwrap(), "3");
+    }
+
+    // Does what the fuzz target does with an entry, none of it may panic
+    fn exercise(line: &str) {
+        if let Ok(mut entry) = HaproxyLogEntry::parse(line) {
+            let _ = entry.colorless();
+            let _ = entry.colorize();
+            serde_json::to_string(&entry).unwrap();
+            let _ = entry.month_number(MonthLocale::French);
+            for name in FIELD_NAMES {
+                let _ = entry.field(name);
+            }
+            entry.parse_query();
+            entry.strip_query();
+        }
+    }
+
+    #[test]
+    fn multi_byte_utf8_in_captures() {
+        for line in [
+            LINE.replace("May", "Mäi."),
+            LINE.replace("applb05", "lb-ä"),
+            LINE.replace("/index.html", "/ü?ä=é&€"),
+            LINE.replace("GET /index.html HTTP/1.1", "€"),
+        ] {
+            exercise(&line);
+        }
+        assert!(HaproxyLogEntry::parse(&LINE.replace("May", "Mäi.")).is_ok());
+    }
+
+    #[test]
+    fn non_ascii_termination_flags() {
+        assert!(HaproxyLogEntry::parse(&LINE.replace(" ---- ", " ä--- ")).is_err());
+        assert!(HaproxyTerminationState::parse("ä---").unwrap().check_flags().is_err());
+    }
+
+    #[test]
+    fn short_termination_states() {
+        for state in ["", "-", "---", "-----"] {
+            assert!(matches!(HaproxyTerminationState::parse(state), Err(ParseError::BadTerminationState { .. })));
+        }
+    }
+
+    #[test]
+    fn numbers_too_large() {
+        for line in [
+            LINE.replace("[3091252]", "[99999999999999999999999]"),
+            LINE.replace(" 1005 ", " 99999999999999999999999 "),
+            LINE.replace("0/0/9/17/26", "0/0/9/17/99999999999999999999999"),
+            LINE.replace(" 200 ", " 99999 "),
+        ] {
+            exercise(&line);
+            assert!(HaproxyLogEntry::parse(&line).is_err());
+        }
+    }
+
+    #[test]
+    fn truncated_lines() {
+        for end in 0..LINE.len() {
+            exercise(&LINE[..end]);
+        }
+    }
+
+    #[test]
+    fn name_palette() {
+        assert!(NAME_PALETTE.iter().all(|&index| (16..232).contains(&index)));
+        assert!(NAME_PALETTE.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(!NAME_PALETTE.contains(&(16 + 5 * 36 + 5 * 6 + 5)));
+        assert_eq!(name_color("silo-backend"), name_color("silo-backend"));
+    }
+
+    #[test]
+    fn accepted_at_has_no_zone() {
+        let entry = HaproxyLogEntry::parse(LINE).unwrap();
+        assert_eq!(serde_json::to_value(&entry).unwrap()["accepted_at"], "2024-05-08T00:08:30.660");
+        assert_eq!(entry.field("accepted_at").unwrap(), "2024-05-08T00:08:30.660");
+    }
 }
@@ -0,0 +1,107 @@
+use colored::Colorize;
+use serde::Serialize;
+
+use l8r::{HaproxyLogEntry, Timer};
+use crate::utils::bold;
+
+pub const DEFAULT_BUCKETS: &[u64] = &[10, 50, 100, 250, 500, 1000, 2500, 5000];
+
+// Counts per bucket, bucket i holds the values below bounds[i] and the last one everything above
+#[derive(Debug, Default)]
+pub struct Histogram {
+    pub counts: Vec<u64>,
+    // Entries without the timer, like TCP entries for server_response
+    pub missing: u64,
+}
+
+impl Histogram {
+    pub fn add(&mut self, entry: &HaproxyLogEntry, timer: Timer, bounds: &[u64]) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; bounds.len() + 1];
+        }
+        match entry.timers.get(timer) {
+            Some(value) => self.counts[bounds.partition_point(|&bound| bound <= value)] += 1,
+            None => self.missing += 1,
+        }
+    }
+
+    pub fn merge(mut self, other: Histogram) -> Histogram {
+        if self.counts.is_empty() {
+            self.counts = other.counts;
+        } else {
+            for (count, other) in self.counts.iter_mut().zip(other.counts) {
+                *count += other;
+            }
+        }
+        self.missing += other.missing;
+        self
+    }
+
+    // Zeros for every bucket when nothing was added at all
+    fn bucket_counts(&self, bounds: &[u64]) -> Vec<u64> {
+        match self.counts.is_empty() {
+            true => vec![0; bounds.len() + 1],
+            false => self.counts.clone(),
+        }
+    }
+}
+
+// An upper bound in milliseconds, with or without the unit
+pub fn parse_bucket(s: &str) -> Result<u64, String> {
+    s.trim().trim_end_matches("ms").parse().map_err(|_| format!("Invalid bucket bound {}", s))
+}
+
+fn bucket_label(bounds: &[u64], i: usize) -> String {
+    match (i.checked_sub(1).map(|j| bounds[j]), bounds.get(i)) {
+        (None, Some(to)) => format!("0-{}ms", to),
+        (Some(from), Some(to)) => format!("{}-{}ms", from, to),
+        (Some(from), None) => format!(">={}ms", from),
+        (None, None) => "all".to_string(),
+    }
+}
+
+fn terminal_width() -> usize {
+    ratatui::crossterm::terminal::size().map_or(80, |(columns, _)| columns as usize)
+}
+
+pub fn output_histogram(histogram: &Histogram, bounds: &[u64], timer: Timer, color: bool) -> String {
+    let labels: Vec<String> = (0..=bounds.len()).map(|i| bucket_label(bounds, i)).collect();
+    let label_width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+    let counts = histogram.bucket_counts(bounds);
+    let max = counts.iter().copied().max().unwrap_or(0);
+    let count_width = max.to_string().len();
+    // What's left of the line after the label and the count
+    let bar_width = terminal_width().saturating_sub(label_width + count_width + 3).max(10);
+
+    let mut result = format!("{}\n", bold(&format!("{} ({} entries)", timer, counts.iter().sum::<u64>()), color));
+    for (label, &count) in labels.iter().zip(&counts) {
+        let length = match max {
+            0 => 0,
+            max => (count as f64 / max as f64 * bar_width as f64).round() as usize,
+        };
+        let bar = "█".repeat(length);
+        let bar = if color { bar.cyan().to_string() } else { bar };
+        result.push_str(&format!("{:>label_width$} {:>count_width$} {}\n", label, count, bar, label_width = label_width, count_width = count_width));
+    }
+    if histogram.missing > 0 {
+        result.push_str(&format!("{} entries without a {} timer\n", histogram.missing, timer));
+    }
+    result
+}
+
+#[derive(Debug, Serialize)]
+struct Bucket {
+    from: u64,
+    // None for the last bucket, which has no upper bound
+    to: Option<u64>,
+    count: u64,
+}
+
+pub fn output_histogram_json(histogram: &Histogram, bounds: &[u64]) -> String {
+    let buckets: Vec<Bucket> = histogram.bucket_counts(bounds).into_iter().enumerate().map(|(i, count)| Bucket {
+        from: i.checked_sub(1).map_or(0, |j| bounds[j]),
+        to: bounds.get(i).copied(),
+        count,
+    }).collect();
+    serde_json::to_string(&serde_json::json!({ "buckets": buckets, "missing": histogram.missing })).unwrap()
+}
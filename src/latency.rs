@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+
+use l8r::HaproxyLogEntry;
+use crate::stats::percentile;
+use crate::utils::bold;
+
+// Every total time seen, overall and per value of the grouping field
+#[derive(Debug, Default)]
+pub struct LatencyReport {
+    pub overall: Vec<u64>,
+    pub groups: BTreeMap<String, Vec<u64>>,
+}
+
+impl LatencyReport {
+    pub fn add(&mut self, entry: &HaproxyLogEntry, group_by: Option<&str>) {
+        self.overall.push(entry.timers.total);
+        if let Some(field) = group_by {
+            let value = entry.field(field).unwrap_or_else(|| "-".to_string());
+            self.groups.entry(value).or_default().push(entry.timers.total);
+        }
+    }
+
+    pub fn merge(mut self, other: LatencyReport) -> LatencyReport {
+        self.overall.extend(other.overall);
+        for (group, totals) in other.groups {
+            self.groups.entry(group).or_default().extend(totals);
+        }
+        self
+    }
+
+    pub fn finish(mut self) -> LatencyReport {
+        self.overall.sort_unstable();
+        for totals in self.groups.values_mut() {
+            totals.sort_unstable();
+        }
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Quantiles {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    pub count: usize,
+    pub p50: Option<u64>,
+    pub p90: Option<u64>,
+    pub p95: Option<u64>,
+    pub p99: Option<u64>,
+    pub max: Option<u64>,
+}
+
+impl Quantiles {
+    // Groups with only a few samples still get every quantile, they just share values
+    fn new(group: Option<&str>, sorted: &[u64]) -> Quantiles {
+        Quantiles {
+            group: group.map(|g| g.to_string()),
+            count: sorted.len(),
+            p50: percentile(sorted, 50.0),
+            p90: percentile(sorted, 90.0),
+            p95: percentile(sorted, 95.0),
+            p99: percentile(sorted, 99.0),
+            max: sorted.last().copied(),
+        }
+    }
+
+    fn values(&self) -> [Option<u64>; 5] {
+        [self.p50, self.p90, self.p95, self.p99, self.max]
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencySummary {
+    #[serde(flatten)]
+    pub overall: Quantiles,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Quantiles>,
+}
+
+impl LatencySummary {
+    pub fn new(report: &LatencyReport) -> LatencySummary {
+        LatencySummary {
+            overall: Quantiles::new(None, &report.overall),
+            groups: report.groups.iter()
+                .map(|(group, totals)| Quantiles::new(Some(group), totals))
+                .collect(),
+        }
+    }
+}
+
+fn format_row(name: &str, quantiles: &Quantiles, width: usize) -> String {
+    let mut row = format!("{:<width$} {:>10}", name, quantiles.count, width = width);
+    for value in quantiles.values() {
+        row.push_str(&format!(" {:>8}", value.map_or("-".to_string(), |v| format!("{}ms", v))));
+    }
+    row
+}
+
+pub fn output_latency_report(summary: &LatencySummary, group_by: Option<&str>, color: bool) -> String {
+    let name = group_by.unwrap_or("");
+    let width = summary.groups.iter()
+        .filter_map(|q| q.group.as_ref().map(|g| g.len()))
+        .chain([name.len(), 3])
+        .max()
+        .unwrap_or(0);
+
+    let mut header = format!("{:<width$} {:>10}", name, "Requests", width = width);
+    for label in ["p50", "p90", "p95", "p99", "Max"] {
+        header.push_str(&format!(" {:>8}", label));
+    }
+
+    let mut result = format!("{}\n", bold(&header, color));
+    result.push_str(&format!("{}\n", bold(&format_row("all", &summary.overall, width), color)));
+    for quantiles in &summary.groups {
+        result.push_str(&format!("{}\n", format_row(quantiles.group.as_deref().unwrap_or(""), quantiles, width)));
+    }
+    result
+}
+
+pub fn output_latency_report_json(summary: &LatencySummary) -> String {
+    serde_json::to_string(summary).unwrap()
+}
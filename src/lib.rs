@@ -0,0 +1,30 @@
+pub mod error;
+pub mod haproxy;
+
+pub use crate::error::ParseError;
+
+pub use crate::haproxy::{
+    HaproxyConnectionCounts, HaproxyLogEntry, HaproxyQueueStats, HaproxySourceAddress, HaproxyTerminationState,
+    HaproxyTerminationStateEntry, HaproxyTimers, LogFormat, MonthLocale, StatusClass, Theme, Timer,
+};
+
+// A single line as JSON, or None when it doesn't match. The entry point exported to JavaScript with the `wasm` feature
+#[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen)]
+pub fn parse_line(line: &str) -> Option<String> {
+    let entry = HaproxyLogEntry::parse(line).ok()?;
+    serde_json::to_string(&entry).ok()
+}
+
+// A batch of lines in their order, parsed on the rayon pool with the `parallel` feature.
+// The entries borrow from `lines`, so the batch has to outlive them
+pub fn parse_many<S: AsRef<str> + Sync>(lines: &[S]) -> Vec<Result<HaproxyLogEntry<'_>, ParseError>> {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        lines.par_iter().map(|line| HaproxyLogEntry::parse(line.as_ref())).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        lines.iter().map(|line| HaproxyLogEntry::parse(line.as_ref())).collect()
+    }
+}
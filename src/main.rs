@@ -1,136 +1,1303 @@
-mod haproxy;
+mod anomaly;
+mod apdex;
+mod bytes;
+mod checkpoint;
+mod columnar;
+mod config;
+mod context;
+mod count;
+mod diff;
+mod extract;
+mod filter;
+mod follow;
+mod geoip;
+mod group;
+mod histogram;
+mod latency;
+mod ordered;
+mod outlier;
+mod output;
+mod preset;
+mod progress;
+mod prometheus;
+mod rate;
+mod sample;
+mod serve;
+mod slo;
+mod stats;
+mod syslog;
+mod template;
+mod termination;
+mod threshold;
+mod tui;
 mod utils;
+mod validate;
+mod watch;
+mod wrap;
 
-use crate::haproxy::HaproxyLogEntry;
-use crate::utils::{is_stdin_redirected, output_table, reset_sigpipe};
+use crate::apdex::{output_apdex, output_apdex_json, Apdex};
+use crate::anomaly::{AnomalyDetector, WARMUP_SAMPLES};
+use crate::bytes::{output_byte_total, output_byte_total_json, ByteTotal};
+use crate::checkpoint::CheckpointedLines;
+use crate::columnar::ParquetSink;
+use crate::config::{load_theme, Config};
+use crate::context::Context;
+use crate::count::{
+    output_counts, output_counts_json, output_cross_tab, output_cross_tab_json, output_top, output_top_json, Counts, CrossTab,
+    TopCounts,
+};
+use crate::diff::{diff_groups, output_diff, output_diff_json, output_group_diff, output_group_diff_json};
+use crate::extract::Extractor;
+use crate::geoip::GeoIp;
+use crate::filter::{parse_term_flags, Filter, NameMatcher, StatusMatcher};
+use crate::follow::{wait_for_file, Follow};
+use l8r::{HaproxyLogEntry, LogFormat, MonthLocale, Timer};
+use l8r::haproxy::{canonical_field_name, FIELD_NAMES};
+use crate::ordered::{Reorder, REORDER_CAPACITY};
+use crate::outlier::{Baselines, OutlierLimits};
+use crate::output::{Envelope, SCHEMA_VERSION, ascii_table_row, columns_header, columns_row, csv_header, fixed_column_widths, html_table_row, output_columns, output_html, output_clickhouse, output_csv, output_logfmt, project, CLICKHOUSE_SCHEMA, output_ascii_table, output_compact, output_influx, output_kv_json};
+use crate::preset::Preset;
+use crate::progress::{Progress, TrackedLines};
+use crate::prometheus::{output_prometheus, Metrics};
+use crate::rate::{output_rate, output_rate_json, Rate};
+use crate::sample::{parse_rate, Sampler};
+use crate::serve::Server;
+use crate::slo::{output_slo, output_slo_json, Objectives, Slo};
+use crate::group::{output_groups, output_groups_json, summarize, Groups};
+use crate::histogram::{output_histogram, output_histogram_json, parse_bucket, Histogram, DEFAULT_BUCKETS};
+use crate::latency::{output_latency_report, output_latency_report_json, LatencyReport, LatencySummary};
+use crate::stats::{output_summary, output_summary_json, Stats, Summary};
+use crate::syslog::strip_syslog;
+use crate::template::Template;
+use crate::termination::{output_term_summary, output_term_summary_json, TermSummary};
+use crate::threshold::{check, output_checks, output_checks_json, Counts as ThresholdCounts, Threshold, ThresholdsExceeded, EXIT_THRESHOLD_EXCEEDED};
+use crate::tui::run_tui;
+use crate::validate::{output_validation, output_validation_json, Validation};
+use crate::watch::run_watch;
+use crate::wrap::JoinWrapped;
+use crate::utils::{is_stdin_redirected, output_geo, output_table, output_table_fields, parse_duration, parse_separator, parse_timestamp, reset_sigpipe, start_pager};
 use std::io::BufRead;
 use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use flate2::bufread::MultiGzDecoder;
 use std::fs::File;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use clap::Parser;
-use regex::Regex;
-use once_cell::sync::Lazy;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use chrono::{FixedOffset, NaiveDateTime};
+use clap::{CommandFactory, Parser};
+use colored::Colorize;
 use serde::Serialize;
 use anyhow::Result;
 use rayon::iter::ParallelBridge;
 use rayon::prelude::ParallelIterator;
 
-static RE: Lazy<Regex> = regex_static::lazy_regex!(r#"^(?P<month>[A-Za-z]{3})\s+(?P<day>\d{1,2})\s+(?P<time>[0-9:]{8})\s+(?P<host>\w+)\s+(?P<process_id>[A-Za-z0-9]+\[\d+\]):\s+(?P<source_ip_port>[0-9.]+:[0-9]+)\s+\[(?P<time_stamp_accepted>.+)\]\s+(?P<frontend_name>\w+)\s+(?P<backend_name>[\w-]+)/(?P<server_name>[-\w]+)\s+(?P<queues_stats>\d+/\d+/\d+/\d+/\d+)\s+(?P<response_code>\d+)\s+(?P<bytes_read>\d+)\s-\s-\s(?P<termination_state>[-\w]{4})\s(?P<conn_counts>\d+/\d+/\d+/\d+/\d+)\s+(?P<queue>\d+/\d+)\s+"(?P<request>.*)"$"#);
+#[derive(clap::ValueEnum, Clone, Copy, Default, Debug)]
+enum ColorChoice {
+    // On a terminal, unless NO_COLOR is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(&self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && atty::is(atty::Stream::Stdout),
+        }
+    }
+}
 
 #[derive(clap::ValueEnum, Clone, Default, Debug, Serialize)]
 #[serde(rename_all = "lowercase")]
 enum OutputFormat {
     Raw,
+    // The source line exactly as it was read
+    #[value(alias = "grep")]
+    Passthrough,
     #[default]
     Color,
+    // One object per line, serde_json escapes control characters so nothing embedded can break a line
+    #[value(alias = "ndjson")]
     Json,
+    JsonPretty,
+    KvJson,
+    SummaryJson,
     Yaml,
     Wide,
+    Influx,
+    Clickhouse,
+    Csv,
+    Logfmt,
+    Parquet,
+    AsciiTable,
+    /// One aligned row per entry, sized to the widest values or to fixed widths with --follow
+    Columns,
+    /// A self-contained HTML page with one table row per entry, written once the input ends
+    Html,
+    /// One line per entry laid out by --output-template
+    Template,
 }
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = "Exit status: 0 on success, 1 on errors, 2 on invalid arguments and 3 when a --fail-if threshold was exceeded.")]
 struct Args {
-    pub file: Option<PathBuf>,
+    /// Files to read in order, - reads stdin in its place, no files at all reads only stdin
+    #[arg(value_name = "FILE")]
+    pub files: Vec<PathBuf>,
+    /// Prefix every printed entry with the name of the file it came from
+    #[arg(short = 'H', long)]
+    pub with_filename: bool,
     #[arg(short, long)]
     pub errors: bool,
     #[arg(short, long)]
     pub terminations: bool,
+    /// Only show entries where this regex matches somewhere in the line, anchor it with ^ and $ to match all of it
     #[arg(short, long)]
     pub matcher: Option<String>,
+    /// Match --matcher regardless of case
+    #[arg(long, requires = "matcher")]
+    pub matcher_ignore_case: bool,
+    /// Match --matcher against this field, like request or path, instead of the whole line
+    #[arg(long, value_name = "FIELD", value_parser = parse_field_name, requires = "matcher")]
+    pub matcher_field: Option<String>,
     #[arg(short, long)]
     pub verbose: bool,
+    /// Don't print how many lines were parsed and how many failed to stderr at the end
+    #[arg(short, long)]
+    pub quiet: bool,
+    /// Append every line that fails to parse to this file as a JSON object with its error kind
+    #[arg(long, value_name = "PATH")]
+    pub emit_failures: Option<PathBuf>,
+    /// Don't page output through $PAGER when stdout is a terminal
+    #[arg(long)]
+    pub no_pager: bool,
+    /// Don't show how far into the files an aggregation is on stderr when stdout and stderr are terminals
+    #[arg(long)]
+    pub no_progress: bool,
+    // Decided before the pager takes over stdout
+    #[arg(skip)]
+    pub progress: bool,
     #[arg(short, long)]
     pub output: Option<OutputFormat>,
+    /// Join the fields of raw and colorless output with this instead of a space, like \t for tab-separated fields
+    #[arg(long, value_name = "SEP", value_parser = parse_separator)]
+    pub raw_separator: Option<String>,
+    /// Only output these fields, by name or dotted path like timers.total, with json, json-pretty, yaml, csv or --serve
+    #[arg(long, value_name = "FIELD[,FIELD]", value_delimiter = ',')]
+    pub fields: Vec<String>,
+    /// Only show these fields in --output wide, in this order
+    #[arg(long, value_name = "FIELD[,FIELD]", value_delimiter = ',', value_parser = parse_field_name)]
+    pub table_fields: Vec<String>,
+    /// Add the version of the record layout as _schema to every json, json-pretty or yaml record
+    #[arg(long)]
+    pub with_schema: bool,
+    /// Print every entry as this line, with fields like {backend_name} or {timers.total} filled in, and
+    /// {{ and }} for literal braces. Implies --output template
+    #[arg(long, value_name = "TEMPLATE")]
+    pub output_template: Option<String>,
+    /// Add the named groups of this regex as fields of json, json-pretty or yaml records when it matches
+    /// the request path, like '/users/(?P<uid>\d+)'. Paths are matched as logged, --normalize-paths
+    /// only applies to aggregations
+    #[arg(long, value_name = "REGEX")]
+    pub extract: Vec<String>,
+    /// Look up the source IP in this MaxMind database and add geo_country and geo_asn to json, json-pretty
+    /// and yaml records, or as lines of wide output. Give it twice for a country and an ASN database
+    #[arg(long, value_name = "MMDB")]
+    pub geoip: Vec<PathBuf>,
+    /// When to color the output, auto colors it on a terminal unless NO_COLOR is set
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    pub color: ColorChoice,
     #[arg(long)]
     #[clap(default_value = "false")]
-    pub serial: bool
+    pub serial: bool,
+    /// Print entries in the order they were read while still parsing in parallel, buffering lines that finish early
+    #[arg(long)]
+    pub ordered: bool,
+    /// Compare aggregate stats of two log files, or their request counts per --group-by value
+    #[arg(long, visible_alias = "compare", num_args = 2, value_names = ["OLD", "NEW"])]
+    pub diff: Option<Vec<PathBuf>>,
+    /// Mark entries slower than the running latency percentile with ⚠ in color output
+    #[arg(long, long_help = format!("Mark entries slower than the running latency percentile with ⚠ in color output.\n\
+        The percentile is estimated while streaming, so the first {} entries are never flagged.", WARMUP_SAMPLES))]
+    pub flag_anomalies: bool,
+    #[arg(long, default_value = "99")]
+    pub anomaly_percentile: f64,
+    /// Mark entries slower than their backend's mean plus --outlier-k standard deviations, with ⚠ in color
+    /// output and slow_outlier in json and yaml. Files are read twice for it, stdin is held in memory
+    #[arg(long)]
+    pub flag_slow_outliers: bool,
+    #[arg(long, value_name = "K", default_value = "3", requires = "flag_slow_outliers")]
+    pub outlier_k: f64,
+    /// Only show entries that read at least this many bytes
+    #[arg(long)]
+    pub min_bytes: Option<u64>,
+    /// Only show entries that read at most this many bytes
+    #[arg(long)]
+    pub max_bytes: Option<u64>,
+    /// Print the sum of bytes read by the matching entries instead of the entries
+    #[arg(long)]
+    pub total_bytes: bool,
+    /// Count paths that only differ in numeric or UUID segments as one, like /users/:id, in aggregations
+    #[arg(long)]
+    pub normalize_paths: bool,
+    /// Only show entries that took at least this many milliseconds
+    #[arg(long, value_name = "MS")]
+    pub slow: Option<u64>,
+    /// The timer --slow is compared with, like server_response to tell slow backends from slow clients
+    #[arg(long, value_name = "TIMER", default_value = "total", requires = "slow")]
+    pub slow_timer: Timer,
+    /// Browse the parsed entries in an interactive terminal UI
+    #[arg(long)]
+    pub tui: bool,
+    /// Only print how many entries match, like grep -c
+    #[arg(short, long)]
+    pub count: bool,
+    /// Count entries per value of a field instead of printing them, two fields give a cross-tabulation
+    #[arg(long, value_name = "FIELD[,FIELD]", value_delimiter = ',', value_parser = parse_field_name)]
+    pub count_by: Vec<String>,
+    /// Count each termination state, like CD, and each of its flags with what they mean instead of printing the entries
+    #[arg(long)]
+    pub term_summary: bool,
+    /// Bucket --count-by counts into fixed time windows, like 30s, 1m or 1h
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, requires = "count_by")]
+    pub group_window: Option<u64>,
+    /// Drop health check requests, the patterns can be changed in the config file
+    #[arg(long)]
+    pub exclude_health_checks: bool,
+    /// Config file to use instead of ~/.config/l8r/config.toml
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Report the Apdex score for a target total time in milliseconds
+    #[arg(long, value_name = "THRESHOLD_MS")]
+    pub apdex: Option<u64>,
+    /// Print the number of requests per second, or per --rps-interval, instead of the entries
+    #[arg(long)]
+    pub rps: bool,
+    /// The length of the --rps intervals, like 10s, 1m or 1h
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "1s", requires = "rps")]
+    pub rps_interval: u64,
+    /// Draw --rps as a sparkline instead of a table
+    #[arg(long, requires = "rps")]
+    pub sparkline: bool,
+    /// Print request, byte and latency metrics per backend in the Prometheus text exposition format
+    #[arg(long)]
+    pub prometheus: bool,
+    /// Only read this fraction of the lines, picked at random, like 0.01 for 1%. Counts of --count,
+    /// --count-by, --top-paths, --top-clients, --rps and --total-bytes are scaled up to estimate all lines
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    pub sample: Option<f64>,
+    /// Seed for --sample, so runs with the same seed keep the same lines
+    #[arg(long, requires = "sample")]
+    pub seed: Option<u64>,
+    /// Strip the RFC 5424 or RFC 3164 priority and header a syslog relay put in front of the lines
+    #[arg(long)]
+    pub syslog: bool,
+    /// Rejoin entries that syslog wrapped over several lines
+    #[arg(long)]
+    pub join_wrapped: bool,
+    /// Decompress gzip on stdin, files are decompressed whenever they start with the gzip magic bytes
+    #[arg(long)]
+    pub gzip: bool,
+    /// Keep reading lines as they are appended to the file, reopening it after rotation or truncation.
+    /// A named pipe is read from every writer in turn instead of ending when the first one closes it
+    #[arg(short, long, requires = "files")]
+    pub follow: bool,
+    /// Follow the file and send every matching entry as a line of JSON to the clients connected to this address
+    #[arg(long, value_name = "ADDR", requires = "files")]
+    pub serve: Option<SocketAddr>,
+    /// Follow the file and show requests per second, the error rate, p95 latency and the busiest backends, redrawn every second
+    #[arg(long, requires = "files")]
+    pub watch: bool,
+    /// How far back --watch looks, like 30s or 5m
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "60s", requires = "watch")]
+    pub watch_window: u64,
+    /// Follow the file and print a one-line summary of every error as it happens
+    #[arg(long, requires = "files")]
+    pub tail_errors: bool,
+    /// The UTC offset HAProxy logs accept dates in, like +02:00, for the absolute timestamps of --output influx
+    #[arg(long, value_name = "OFFSET", default_value = "+00:00")]
+    pub utc_offset: FixedOffset,
+    /// Locale the syslog prefix was written in, non-C month names like mai or mär need this
+    #[arg(long, value_name = "LOCALE", default_value = "C")]
+    pub locale: MonthLocale,
+    /// Check that every line parses and exit non-zero if too many don't
+    #[arg(long)]
+    pub validate: bool,
+    /// Exit with status 3 when more matching entries than this are errors, as a percentage like 1% or a count like 100
+    #[arg(long, value_name = "LIMIT")]
+    pub fail_if_errors_over: Option<Threshold>,
+    /// Exit with status 3 when more matching entries than this got a 5xx, as a percentage like 1% or a count like 100
+    #[arg(long = "fail-if-5xx-over", value_name = "LIMIT")]
+    pub fail_if_5xx_over: Option<Threshold>,
+    /// Percentage of lines --validate lets fail to parse before failing itself
+    #[arg(long, value_name = "PCT", default_value = "0", requires = "validate")]
+    pub max_unparsed_pct: f64,
+    /// Worker threads for parallel parsing, 0 uses every core
+    #[arg(long, value_name = "N", default_value = "0")]
+    pub threads: usize,
+    /// Drop the query string from requests before printing them
+    #[arg(long)]
+    pub strip_query: bool,
+    /// Include the query parameters of each request as a map in JSON and YAML output
+    #[arg(long)]
+    pub parse_query: bool,
+    /// Print request, error, status and latency totals instead of the entries
+    #[arg(long)]
+    pub stats: bool,
+    /// Print p50, p90, p95, p99 and max of the total time instead of the entries
+    #[arg(long)]
+    pub latency_report: bool,
+    /// Print a histogram of one timer, like total or server_response, instead of the entries
+    #[arg(long, value_name = "TIMER")]
+    pub histogram: Option<Timer>,
+    /// Upper bounds of the --histogram buckets in milliseconds
+    #[arg(long, value_name = "MS[,MS]", value_delimiter = ',', value_parser = parse_bucket, requires = "histogram")]
+    pub buckets: Vec<u64>,
+    /// Print the N most requested paths, without their query strings, instead of the entries
+    #[arg(long, value_name = "N")]
+    pub top_paths: Option<usize>,
+    /// Print the N source IPs with the most requests instead of the entries
+    #[arg(long, value_name = "N", conflicts_with = "top_paths")]
+    pub top_clients: Option<usize>,
+    /// Also sum the bytes read per path or client in --top-paths and --top-clients
+    #[arg(long)]
+    pub top_bytes: bool,
+    /// Print requests, errors and the mean and median total time per value of a field, like backend,
+    /// or break --latency-report, --top-paths or --top-clients down by it
+    #[arg(long, value_name = "FIELD", value_parser = parse_field_name)]
+    pub group_by: Option<String>,
+    /// Wait for the file to be created instead of failing when it doesn't exist yet
+    #[arg(long, requires = "files")]
+    pub wait: bool,
+    /// Give up on --wait after this long, like 30s or 5m
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, requires = "wait")]
+    pub wait_timeout: Option<u64>,
+    /// Only show the entries that fail the filters, as a whole, instead of those that pass them
+    #[arg(long, visible_alias = "invert-match")]
+    pub invert: bool,
+    /// Only show entries with one of these status codes, like 502, 5xx or 500-504
+    #[arg(long, value_name = "STATUS[,STATUS]", value_delimiter = ',')]
+    pub status: Vec<StatusMatcher>,
+    /// Only show entries for this backend, or those matching a regex prefixed with ~, can be repeated
+    #[arg(long, value_name = "NAME")]
+    pub backend: Vec<NameMatcher>,
+    /// Only show entries for this frontend, or those matching a regex prefixed with ~, can be repeated
+    #[arg(long, value_name = "NAME")]
+    pub frontend: Vec<NameMatcher>,
+    /// Only show entries for this server, or those matching a regex prefixed with ~, can be repeated
+    #[arg(long, value_name = "NAME")]
+    pub server: Vec<NameMatcher>,
+    /// Only show entries logged by the process with this PID, for files several processes log to
+    #[arg(long, value_name = "PID[,PID]", value_delimiter = ',')]
+    pub pid: Vec<u64>,
+    /// Only show entries where HAProxy retried the connection to a server
+    #[arg(long)]
+    pub retried: bool,
+    /// Only show entries that were redispatched to another server after failed retries
+    #[arg(long)]
+    pub redispatched: bool,
+    /// Only show entries that terminated for one of these reasons, like C for a client abort or s for a server timeout
+    #[arg(long, value_name = "FLAGS", value_parser = parse_term_flags(0))]
+    pub term_reason: Option<String>,
+    /// Only show entries whose session was in one of these states when it ended, like H for waiting on response headers
+    #[arg(long, value_name = "FLAGS", value_parser = parse_term_flags(1))]
+    pub term_state: Option<String>,
+    /// Only show entries with one of these persistence cookie flags, never matches tcplog entries
+    #[arg(long, value_name = "FLAGS", value_parser = parse_term_flags(2))]
+    pub term_cookie: Option<String>,
+    /// Only show entries with one of these cookie operation flags, never matches tcplog entries
+    #[arg(long, value_name = "FLAGS", value_parser = parse_term_flags(3))]
+    pub term_operations: Option<String>,
+    /// Print the CREATE TABLE statement matching --output clickhouse and exit
+    #[arg(long)]
+    pub clickhouse_schema: bool,
+    /// Print N entries around every match, like grep -C
+    #[arg(short = 'C', long, value_name = "N")]
+    pub context: Option<usize>,
+    /// Print N entries after every match, overrides --context
+    #[arg(short = 'A', long, value_name = "N")]
+    pub after_context: Option<usize>,
+    /// Print N entries before every match, overrides --context
+    #[arg(short = 'B', long, value_name = "N")]
+    pub before_context: Option<usize>,
+    /// Give every backend and server its own color, derived from its name
+    #[arg(long)]
+    pub color_by_name: bool,
+    /// Report how many requests finished within this many milliseconds against --slo-target
+    #[arg(long, value_name = "MS")]
+    pub slo_latency: Option<u64>,
+    /// Percentage of requests that has to meet --slo-latency
+    #[arg(long, value_name = "PCT", default_value = "99", requires = "slo_latency")]
+    pub slo_target: f64,
+    /// Report how many requests were served without a 5xx or aborted session against this percentage
+    #[arg(long, value_name = "PCT")]
+    pub slo_availability: Option<f64>,
+    /// Only process lines added since the last run with the same checkpoint file, and update it
+    #[arg(long, value_name = "FILE", requires = "files", conflicts_with = "follow")]
+    pub checkpoint: Option<PathBuf>,
+    /// Only show entries accepted at or after this time, like 08/May/2024:00:08:00
+    #[arg(long, value_name = "TIME", value_parser = parse_timestamp)]
+    pub since: Option<NaiveDateTime>,
+    /// Only show entries accepted before this time
+    #[arg(long, value_name = "TIME", value_parser = parse_timestamp)]
+    pub until: Option<NaiveDateTime>,
+    /// Only parse lines in this log format instead of detecting http or tcp per line
+    #[arg(long, value_name = "FORMAT")]
+    pub format: Option<LogFormat>,
+    /// Parse lines with this regex instead, for a custom log-format. It needs the same named groups as the built in ones
+    #[arg(long, value_name = "REGEX", env = "L8R_LOG_FORMAT_REGEX", conflicts_with = "format")]
+    pub log_format_regex: Option<String>,
+    /// Parse lines in one of the log formats HAProxy ships with, clf lines are mapped onto httplog ones
+    #[arg(long, value_name = "PRESET", conflicts_with_all = ["format", "log_format_regex"])]
+    pub preset: Option<Preset>,
+    /// Count lines with termination flags HAProxy doesn't document as failing to parse
+    #[arg(long)]
+    pub strict: bool,
+}
+
+// Hidden `l8r completions <SHELL>`, dispatched before `Args` so it doesn't need an input file
+#[derive(Parser, Debug)]
+#[command(name = "l8r completions", about = "Print a shell completion script")]
+struct CompletionsArgs {
+    pub shell: clap_complete::Shell,
 }
 
+impl Args {
+    fn color(&self) -> bool {
+        matches!(self.output, Some(OutputFormat::Color) | None) && colored::control::SHOULD_COLORIZE.should_colorize()
+    }
+}
+
+fn parse_field_name(s: &str) -> Result<String, String> {
+    match canonical_field_name(s) {
+        Some(_) => Ok(s.to_string()),
+        None => Err(format!("Unknown field, expected one of: {}", FIELD_NAMES.join(", "))),
+    }
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
 enum Reader {
     File(BufReader<File>),
     Stdin(BufReader<std::io::Stdin>),
+    GzipFile(BufReader<MultiGzDecoder<BufReader<File>>>),
+    GzipStdin(BufReader<MultiGzDecoder<BufReader<std::io::Stdin>>>),
+    Follow(Follow),
+    Checkpointed(CheckpointedLines),
+    // Stdin read ahead of time, for modes that need to go over it more than once
+    Buffered(Arc<Vec<String>>),
+}
+
+impl Reader {
+    // `created` files are followed from their first line, nothing in them has been seen yet
+    fn open(file: Option<&PathBuf>, follow: bool, created: bool, gzip: bool) -> Result<Reader, Box<dyn std::error::Error>> {
+        match file {
+            Some(file) if follow => Ok(Reader::Follow(Follow::open(file, created)?)),
+            Some(file) => {
+                let file = File::open(file)?;
+                let mut reader = BufReader::new(file);
+                match reader.fill_buf()?.starts_with(GZIP_MAGIC) {
+                    true => Ok(Reader::GzipFile(BufReader::new(MultiGzDecoder::new(reader)))),
+                    false => Ok(Reader::File(reader)),
+                }
+            }
+            None => {
+                if is_stdin_redirected()? {
+                    let mut reader = BufReader::new(std::io::stdin());
+                    // Only peeked for with --gzip, so plain stdin never blocks on the first read
+                    match gzip && reader.fill_buf()?.starts_with(GZIP_MAGIC) {
+                        true => Ok(Reader::GzipStdin(BufReader::new(MultiGzDecoder::new(reader)))),
+                        false => Ok(Reader::Stdin(reader)),
+                    }
+                } else {
+                    Err("No input provided".into())
+                }
+            }
+        }
+    }
+
+    fn lines(self) -> Box<dyn Iterator<Item = String> + Send> {
+        match self {
+            Reader::File(reader) => Box::new(reader.lines().map_while(Result::ok)),
+            Reader::Stdin(reader) => Box::new(reader.lines().map_while(Result::ok)),
+            Reader::GzipFile(reader) => Box::new(reader.lines().map_while(Result::ok)),
+            Reader::GzipStdin(reader) => Box::new(reader.lines().map_while(Result::ok)),
+            Reader::Follow(follow) => Box::new(follow),
+            Reader::Checkpointed(lines) => Box::new(lines),
+            Reader::Buffered(lines) => Box::new((0..lines.len()).map(move |i| lines[i].clone())),
+        }
+    }
+
+    // A handle on the file being read that shares its read position
+    fn position(&self) -> Option<File> {
+        match self {
+            Reader::File(reader) => reader.get_ref().try_clone().ok(),
+            Reader::GzipFile(reader) => reader.get_ref().get_ref().get_ref().try_clone().ok(),
+            _ => None,
+        }
+    }
+}
+
+type Lines = Box<dyn Iterator<Item = String> + Send>;
+type Input = (Arc<str>, Lines);
+
+// Every input, in the order they were given, with the name `--with-filename` prints for it
+struct Inputs {
+    inputs: Vec<Input>,
+    progress: Option<Progress>,
+}
+
+impl Inputs {
+    fn open(args: &Args, reader: impl Fn(Option<&PathBuf>) -> Result<Reader, Box<dyn std::error::Error>>) -> Result<Inputs, Box<dyn std::error::Error>> {
+        let open = |file: Option<&PathBuf>| -> Result<(Input, Option<File>), Box<dyn std::error::Error>> {
+            let name: Arc<str> = file.map_or("(standard input)".into(), |file| file.to_string_lossy().into());
+            let reader = reader(file)?;
+            let position = reader.position();
+            Ok(((name, input_lines(reader, args)), position))
+        };
+        if args.files.iter().filter(|file| file.as_os_str() == "-").count() > 1 {
+            return Err("- can only be given once, stdin can't be read twice".into());
+        }
+
+        let opened: Vec<(Input, Option<File>)> = match args.files.is_empty() {
+            true => vec![open(None)?],
+            false => args.files.iter()
+                .map(|file| open(Some(file).filter(|file| file.as_os_str() != "-")))
+                .collect::<Result<_, _>>()?,
+        };
+        let (inputs, positions): (Vec<Input>, Vec<Option<File>>) = opened.into_iter().unzip();
+        // Stdin has no size, so there's no telling how far along it is
+        let progress = match args.progress {
+            true => positions.into_iter().collect::<Option<Vec<File>>>().and_then(Progress::new),
+            false => None,
+        };
+        Ok(Inputs { inputs, progress })
+    }
+
+    // Aggregations read through here, and they print nothing before the end to draw over
+    fn lines(self) -> Lines {
+        let lines = self.inputs.into_iter().flat_map(|(_, lines)| lines);
+        match self.progress {
+            Some(progress) => Box::new(TrackedLines::new(lines, progress.start())),
+            None => Box::new(lines),
+        }
+    }
+
+    fn named_lines(self) -> Box<dyn Iterator<Item = (Arc<str>, String)> + Send> {
+        Box::new(self.inputs.into_iter().flat_map(|(name, lines)| lines.map(move |line| (name.clone(), line))))
+    }
+}
+
+// Every mode reads through here, so input handling options apply to all of them
+fn input_lines(reader: Reader, args: &Args) -> Lines {
+    // Before anything else touches the line, skipped lines should cost as little as possible
+    let lines = match args.sample {
+        Some(rate) => {
+            let sampler = Sampler::new(rate, args.seed);
+            Box::new(reader.lines().zip(0..).filter(move |(_, index)| sampler.keep(*index)).map(|(line, _)| line))
+        }
+        None => reader.lines()
+    };
+    let lines = match args.syslog {
+        true => Box::new(lines.map(strip_syslog)),
+        false => lines
+    };
+    match args.join_wrapped {
+        true => Box::new(JoinWrapped::new(lines)),
+        false => lines
+    }
+}
+
+// Every line read ends up in one of these. Skipped lines didn't match --matcher, so they were
+// never parsed and can't be told apart into parsed and failed ones
+#[derive(Default)]
+struct LineCounts {
+    parsed: AtomicU64,
+    failed: AtomicU64,
+    skipped: AtomicU64,
+}
+
+impl LineCounts {
+    fn count(&self, counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn report(&self, quiet: bool) {
+        if quiet {
+            return
+        }
+        let skipped = match self.skipped.load(Ordering::Relaxed) {
+            0 => "".to_string(),
+            skipped => format!(", {} skipped by --matcher", skipped),
+        };
+        eprintln!("Parsed {} lines, {} failed to match{}", self.parsed.load(Ordering::Relaxed), self.failed.load(Ordering::Relaxed), skipped);
+    }
+}
+
+// Folds every entry passing the filter into an accumulator, merging the per-thread ones when parallel
+fn aggregate<T, A, M>(lines: Box<dyn Iterator<Item = String> + Send>, filter: &Filter, counts: &LineCounts, serial: bool, add: A, merge: M) -> T
+where
+    T: Default + Send,
+    A: Fn(&mut T, &HaproxyLogEntry) + Sync,
+    M: Fn(T, T) -> T + Sync + Send,
+{
+    let fold = |mut acc: T, line: String| {
+        if !filter.matches_line(&line) {
+            counts.count(&counts.skipped);
+            return acc
+        }
+
+        match filter.parse(&line, &filter.map_line(&line)) {
+            Ok(mut entry) => {
+                counts.count(&counts.parsed);
+                if filter.matches(&entry) {
+                    filter.normalize(&mut entry);
+                    add(&mut acc, &entry);
+                }
+            }
+            Err(_) => counts.count(&counts.failed),
+        }
+        acc
+    };
+
+    if serial {
+        lines.fold(T::default(), fold)
+    } else {
+        lines.par_bridge().fold(T::default, fold).reduce(T::default, merge)
+    }
 }
 
+fn collect_stats(lines: Box<dyn Iterator<Item = String> + Send>, filter: &Filter, counts: &LineCounts, serial: bool) -> Stats {
+    aggregate(lines, filter, counts, serial, |stats: &mut Stats, entry| stats.add(entry), Stats::merge).finish()
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     reset_sigpipe();
-    let args = Args::parse();
+    if std::env::args_os().nth(1).is_some_and(|arg| arg == "completions") {
+        let completions = CompletionsArgs::parse_from(std::env::args_os().skip(1));
+        clap_complete::generate(completions.shell, &mut Args::command(), "l8r", &mut std::io::stdout());
+        return Ok(())
+    }
 
-    let matcher: Option<Regex> = match args.matcher {
-        Some(m) => Some(Regex::new(&m)?),
-        None => None
+    let mut args = Args::parse();
+    // Everything printed with `colored` follows the choice from here on
+    colored::control::set_override(args.color.enabled());
+    if args.tail_errors {
+        args.follow = true;
+        args.errors = true;
+    }
+    if args.output_template.is_some() && args.output.is_none() {
+        args.output = Some(OutputFormat::Template);
+    }
+    if args.serve.is_some() || args.watch {
+        args.follow = true;
+    }
+    // Lines have to come out in the order they were written
+    if args.follow || args.context.is_some() || args.after_context.is_some() || args.before_context.is_some() {
+        args.serial = true;
+    }
+
+    // Per-line output streams, so the bar is only drawn by aggregations, see `Inputs::lines`.
+    // It stays hidden when stderr isn't a terminal either
+    args.progress = !args.quiet && !args.no_progress && !args.follow && !args.tui && atty::is(atty::Stream::Stdout);
+
+    // Streams and the TUI need the terminal itself, parquet is refused on one further down
+    let paged = !args.no_pager && !args.follow && !args.tui && !matches!(args.output, Some(OutputFormat::Parquet))
+        && atty::is(atty::Stream::Stdout);
+    let pager = match paged {
+        true => start_pager(),
+        false => None
     };
 
-    let reader: Reader = match &args.file {
-        Some(file) => {
-            let file = File::open(file)?;
-            let reader = BufReader::new(file);
-            Reader::File(reader)
+    // Everything runs inside the pool so par_bridge never touches the global one
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(args.threads).build()?;
+    let exceeded = pool.install(|| match run(args) {
+        Err(e) if e.is::<ThresholdsExceeded>() => Ok(true),
+        result => result.map(|_| false).map_err(|e| e.to_string()),
+    })?;
+    if exceeded {
+        // Exiting skips destructors, and the pager has to see the report through first
+        drop(pager);
+        std::process::exit(EXIT_THRESHOLD_EXCEEDED);
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    if args.clickhouse_schema {
+        println!("{}", CLICKHOUSE_SCHEMA);
+        return Ok(())
+    }
+
+    if !args.fields.is_empty() && args.serve.is_none() && !matches!(args.output, Some(OutputFormat::Json | OutputFormat::JsonPretty | OutputFormat::Yaml | OutputFormat::Csv)) {
+        return Err("--fields only works with --output json, json-pretty, yaml or csv, or --serve".into());
+    }
+    if args.output_template.is_some() != matches!(args.output, Some(OutputFormat::Template)) {
+        return Err("--output template and --output-template go together".into());
+    }
+    // Before reading anything, so a typo in a placeholder fails right away
+    let template = args.output_template.as_deref().map(Template::parse).transpose()?;
+    if args.with_schema && !matches!(args.output, Some(OutputFormat::Json | OutputFormat::JsonPretty | OutputFormat::Yaml)) {
+        return Err("--with-schema only works with --output json, json-pretty or yaml".into());
+    }
+    if !args.extract.is_empty() && !matches!(args.output, Some(OutputFormat::Json | OutputFormat::JsonPretty | OutputFormat::Yaml)) {
+        return Err("--extract only works with --output json, json-pretty or yaml".into());
+    }
+    if !args.geoip.is_empty() && !matches!(args.output, Some(OutputFormat::Json | OutputFormat::JsonPretty | OutputFormat::Yaml | OutputFormat::Wide)) {
+        return Err("--geoip only works with --output json, json-pretty, yaml or wide".into());
+    }
+    if !args.table_fields.is_empty() && !matches!(args.output, Some(OutputFormat::Wide)) {
+        return Err("--table-fields only works with --output wide".into());
+    }
+    // These hold on to matches until the end or send them elsewhere, so there's nothing to print around
+    let context = args.context.is_some() || args.after_context.is_some() || args.before_context.is_some();
+    let buffered = matches!(args.output, Some(OutputFormat::AsciiTable | OutputFormat::Html | OutputFormat::Parquet))
+        || (matches!(args.output, Some(OutputFormat::Columns)) && !args.follow);
+    if context && (buffered || args.serve.is_some()) {
+        return Err("-C, -A and -B don't work with --output ascii-table, columns, html or parquet, or --serve".into());
+    }
+
+    let config = Config::load(args.config.as_deref())?;
+    let theme = load_theme()?;
+    let filter = Filter::from_args(&args, &config)?;
+    let line_counts = LineCounts::default();
+
+    if let Some(files) = &args.diff {
+        let lines = |file: &PathBuf| Reader::open(Some(file), false, false, args.gzip).map(|reader| input_lines(reader, &args));
+        if let Some(field) = &args.group_by {
+            let groups = |lines: Lines| aggregate(lines, &filter, &line_counts, args.serial, |groups: &mut Groups, entry| {
+                groups.add(entry, field)
+            }, Groups::merge);
+            let diffs = diff_groups(&groups(lines(&files[0])?), &groups(lines(&files[1])?));
+            match args.output {
+                Some(OutputFormat::Json) => println!("{}", output_group_diff_json(&diffs)),
+                _ => print!("{}", output_group_diff(&diffs, field, args.color())),
+            }
+            line_counts.report(args.quiet);
+            return Ok(())
         }
-        None => {
-            if is_stdin_redirected()? {
-                let reader = BufReader::new(std::io::stdin());
-                Reader::Stdin(reader)
-            } else {
-                return Err("No input provided".into());
+
+        let old = collect_stats(lines(&files[0])?, &filter, &line_counts, args.serial);
+        let new = collect_stats(lines(&files[1])?, &filter, &line_counts, args.serial);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_diff_json(&old, &new)),
+            _ => print!("{}", output_diff(&old, &new, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    // These keep state about a single file, or read from where it ends
+    if (args.follow || args.wait || args.checkpoint.is_some()) && (args.files.len() > 1 || args.files[0].as_os_str() == "-") {
+        return Err("--follow, --tail-errors, --wait and --checkpoint only work with a single file".into());
+    }
+
+    let created = match (args.files.first(), args.wait) {
+        (Some(file), true) => wait_for_file(file, args.wait_timeout.map(Duration::from_secs))?,
+        _ => false
+    };
+    // The baseline needs a pass over everything before the first entry can be flagged
+    if args.flag_slow_outliers && (args.follow || args.checkpoint.is_some()) {
+        return Err("--flag-slow-outliers can't be used with --follow or --checkpoint, it reads the input twice".into());
+    }
+    let buffered_stdin = match args.flag_slow_outliers && (args.files.is_empty() || args.files.iter().any(|file| file.as_os_str() == "-")) {
+        true => Some(Arc::new(Reader::open(None, false, false, args.gzip)?.lines().collect::<Vec<String>>())),
+        false => None
+    };
+    let open_inputs = || Inputs::open(&args, |file| match (file, &args.checkpoint, &buffered_stdin) {
+        (None, _, Some(lines)) => Ok(Reader::Buffered(lines.clone())),
+        (Some(file), Some(checkpoint), _) => Ok(Reader::Checkpointed(CheckpointedLines::open(file, checkpoint)?)),
+        (file, _, _) => Reader::open(file, args.follow, created, args.gzip),
+    });
+    let outliers: Option<OutlierLimits> = match args.flag_slow_outliers {
+        true => Some(aggregate(open_inputs()?.lines(), &filter, &LineCounts::default(), args.serial, |baselines: &mut Baselines, entry| {
+            baselines.add(entry)
+        }, Baselines::merge).limits(args.outlier_k)),
+        false => None
+    };
+    let inputs = open_inputs()?;
+
+    // An estimate of a count in every line from the one in the sample
+    let scale = |count: u64| match args.sample {
+        Some(rate) => (count as f64 / rate).round() as u64,
+        None => count
+    };
+    if let Some(rate) = args.sample {
+        if !args.quiet {
+            eprintln!("Sampling {:.2}% of lines, counts are scaled up by {:.1}x", rate * 100.0, 1.0 / rate);
+        }
+    }
+
+    if args.watch {
+        run_watch(inputs.lines(), &filter, Duration::from_secs(args.watch_window), colored::control::SHOULD_COLORIZE.should_colorize());
+        return Ok(())
+    }
+
+    // Filters don't apply, the point is to check every line
+    if args.validate {
+        let validation = Validation::check(inputs.lines(), &filter);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_validation_json(&validation, args.max_unparsed_pct)),
+            _ => print!("{}", output_validation(&validation, args.max_unparsed_pct, args.color())),
+        }
+        if !validation.passed(args.max_unparsed_pct) {
+            return Err(format!("{} of {} lines failed to parse", validation.unparsed(), validation.total).into());
+        }
+        return Ok(())
+    }
+
+    if args.fail_if_errors_over.is_some() || args.fail_if_5xx_over.is_some() {
+        let counts = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |counts: &mut ThresholdCounts, entry| {
+            counts.add(entry)
+        }, ThresholdCounts::merge);
+        let checks = check(&counts, args.fail_if_errors_over, args.fail_if_5xx_over);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_checks_json(&counts, &checks)),
+            _ => print!("{}", output_checks(&counts, &checks, args.color())),
+        }
+        if checks.iter().any(|check| check.exceeded) {
+            line_counts.report(args.quiet);
+            return Err(ThresholdsExceeded.into());
+        }
+        return Ok(())
+    }
+
+    if args.count {
+        let count = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |count: &mut u64, _| *count += 1, |a, b| a + b);
+        println!("{}", scale(count));
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if let [row_field, column_field] = args.count_by.as_slice() {
+        if args.group_window.is_some() {
+            return Err("--group-window only supports a single --count-by field".into());
+        }
+        let mut tab = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |tab: &mut CrossTab, entry| {
+            tab.add(entry, row_field, column_field)
+        }, CrossTab::merge);
+        tab.scale(scale);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_cross_tab_json(&tab)),
+            _ => print!("{}", output_cross_tab(&tab, row_field, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if args.count_by.len() > 2 {
+        return Err("--count-by takes at most two fields".into());
+    }
+
+    if args.term_summary {
+        let mut summary = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |summary: &mut TermSummary, entry| {
+            summary.add(entry)
+        }, TermSummary::merge);
+        summary.scale(scale);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_term_summary_json(&summary)),
+            _ => print!("{}", output_term_summary(&summary, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if let Some(field) = args.count_by.first() {
+        let mut counts = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |counts: &mut Counts, entry| {
+            counts.add(entry, field, args.group_window)
+        }, Counts::merge);
+        counts.scale(scale);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_counts_json(&counts, args.group_window.is_some())),
+            _ => print!("{}", output_counts(&counts, field, args.group_window.is_some(), args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if args.stats || matches!(args.output, Some(OutputFormat::SummaryJson)) {
+        let summary = Summary::new(&collect_stats(inputs.lines(), &filter, &line_counts, args.serial));
+        match args.output {
+            Some(OutputFormat::Json) | Some(OutputFormat::SummaryJson) => println!("{}", output_summary_json(&summary)),
+            _ => print!("{}", output_summary(&summary, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if args.slo_latency.is_some() || args.slo_availability.is_some() {
+        let objectives = Objectives {
+            latency: args.slo_latency.map(|threshold| (threshold, args.slo_target)),
+            availability: args.slo_availability,
+        };
+        let slo = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |slo: &mut Slo, entry| {
+            slo.add(entry, &objectives)
+        }, Slo::merge);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_slo_json(&slo, &objectives)),
+            _ => print!("{}", output_slo(&slo, &objectives, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    let top_field = match (args.top_paths, args.top_clients) {
+        (Some(n), _) => Some((n, "request.path", "path", "Path")),
+        (None, Some(n)) => Some((n, "source_ip", "source_ip", "Client")),
+        (None, None) => None
+    };
+    if let Some((n, field, key, label)) = top_field {
+        let group_by = args.group_by.as_deref();
+        let mut top = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |top: &mut TopCounts, entry| {
+            top.add(entry, field, group_by)
+        }, TopCounts::merge);
+        top.scale(scale);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_top_json(&top, n, key, args.top_bytes)),
+            _ => print!("{}", output_top(&top, n, label, group_by, args.top_bytes, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if let Some(timer) = args.histogram {
+        let bounds = match args.buckets.as_slice() {
+            [] => DEFAULT_BUCKETS,
+            bounds if bounds.windows(2).any(|w| w[0] >= w[1]) => return Err("--buckets have to be increasing".into()),
+            bounds => bounds,
+        };
+        let histogram = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |histogram: &mut Histogram, entry| {
+            histogram.add(entry, timer, bounds)
+        }, Histogram::merge);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_histogram_json(&histogram, bounds)),
+            _ => print!("{}", output_histogram(&histogram, bounds, timer, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if args.latency_report {
+        let group_by = args.group_by.as_deref();
+        let report = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |report: &mut LatencyReport, entry| {
+            report.add(entry, group_by)
+        }, LatencyReport::merge).finish();
+        let summary = LatencySummary::new(&report);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_latency_report_json(&summary)),
+            _ => print!("{}", output_latency_report(&summary, group_by, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if let Some(field) = &args.group_by {
+        let groups = summarize(aggregate(inputs.lines(), &filter, &line_counts, args.serial, |groups: &mut Groups, entry| {
+            groups.add(entry, field)
+        }, Groups::merge));
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_groups_json(&groups)),
+            _ => print!("{}", output_groups(&groups, field, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if let Some(threshold) = args.apdex {
+        let apdex = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |apdex: &mut Apdex, entry| {
+            apdex.add(entry, threshold)
+        }, Apdex::merge);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_apdex_json(&apdex, threshold)),
+            _ => print!("{}", output_apdex(&apdex, threshold, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if args.rps {
+        let interval = args.rps_interval;
+        let mut rate = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |rate: &mut Rate, entry| {
+            rate.add(entry, interval)
+        }, Rate::merge);
+        rate.scale(scale);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_rate_json(&rate, interval)),
+            _ => print!("{}", output_rate(&rate, interval, args.sparkline, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if args.total_bytes {
+        let mut total = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |total: &mut ByteTotal, entry| {
+            total.add(entry)
+        }, ByteTotal::merge);
+        total.scale(scale);
+        match args.output {
+            Some(OutputFormat::Json) => println!("{}", output_byte_total_json(&total)),
+            _ => print!("{}", output_byte_total(&total, args.color())),
+        }
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if args.prometheus {
+        let metrics = aggregate(inputs.lines(), &filter, &line_counts, args.serial, |metrics: &mut Metrics, entry| {
+            metrics.add(entry)
+        }, Metrics::merge);
+        print!("{}", output_prometheus(&metrics));
+        line_counts.report(args.quiet);
+        return Ok(())
+    }
+
+    if args.tui {
+        let lines: Vec<(String, String)> = inputs.lines()
+            .filter(|line| filter.matches_line(line))
+            .map(|line| {
+                let mapped = filter.map_line(&line).into_owned();
+                (line, mapped)
+            })
+            .filter(|(line, mapped)| filter.parse(line, mapped).is_ok_and(|entry| filter.matches(&entry)))
+            .collect();
+        return run_tui(&lines, &filter, theme)
+    }
+
+    let parquet = match args.output {
+        Some(OutputFormat::Parquet) => {
+            if atty::is(atty::Stream::Stdout) {
+                return Err("Refusing to write parquet to a terminal, redirect stdout to a file".into());
             }
+            Some(Mutex::new(ParquetSink::new(std::io::stdout())?))
         }
+        _ => None
+    };
+    // Formats that need every row before they can print anything
+    let table_rows: Mutex<Vec<Vec<String>>> = Mutex::new(vec![]);
+    let detector = match args.flag_anomalies {
+        true => Some(AnomalyDetector::new(args.anomaly_percentile)?),
+        false => None
+    };
+
+    let failures = match &args.emit_failures {
+        Some(path) => Some(Mutex::new(BufWriter::new(File::options().create(true).append(true).open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?))),
+        None => None
     };
 
-        let parser = |line: String| {
-            if let Some(ref matcher) = matcher {
-                if !matcher.is_match(&line) {
-                    return
+    let server = match args.serve {
+        Some(addr) => {
+            let server = Server::bind(addr).map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+            if !args.quiet {
+                eprintln!("Serving entries on {}", addr);
+            }
+            Some(server)
+        }
+        None => None
+    };
+
+    let context = match (args.before_context.or(args.context), args.after_context.or(args.context)) {
+        (None, None) => None,
+        (before, after) => Some(Mutex::new(Context::new(before.unwrap_or(0), after.unwrap_or(0)))),
+    };
+
+    let table_theme = colored::control::SHOULD_COLORIZE.should_colorize().then_some(&theme);
+    // Streams print every row as it comes, so their columns can't be sized to the widest value
+    let column_widths = fixed_column_widths();
+    let stream_columns = matches!(args.output, Some(OutputFormat::Columns)) && args.follow;
+
+    let schema = args.with_schema.then_some(SCHEMA_VERSION);
+    let separator = args.raw_separator.as_deref().unwrap_or(" ");
+
+    let extractor = Extractor::new(&args.extract)?;
+    let geoip = match args.geoip.is_empty() {
+        true => None,
+        false => Some(GeoIp::open(&args.geoip)?),
+    };
+    let format_entry = |entry: &HaproxyLogEntry, anomalous: bool, outlier: Option<bool>| {
+        if args.tail_errors {
+            return output_compact(entry, &theme)
+        }
+        let extracted = extractor.extract(entry);
+        let geo = geoip.as_ref().and_then(|geoip| geoip.lookup(entry.source.ip));
+
+        match args.output {
+            Some(OutputFormat::Raw) => entry.colorless_with(separator),
+            Some(OutputFormat::Passthrough) => entry.raw_line.to_string(),
+            Some(OutputFormat::Json) if !args.fields.is_empty() => serde_json::to_string(&Envelope { schema, record: &project(entry, &args.fields), slow_outlier: outlier, extracted, geo }).unwrap(),
+            Some(OutputFormat::JsonPretty) if !args.fields.is_empty() => serde_json::to_string_pretty(&Envelope { schema, record: &project(entry, &args.fields), slow_outlier: outlier, extracted, geo }).unwrap(),
+            Some(OutputFormat::Yaml) if !args.fields.is_empty() => format!("---\n{}", serde_yaml::to_string(&Envelope { schema, record: &project(entry, &args.fields), slow_outlier: outlier, extracted, geo }).unwrap()),
+            Some(OutputFormat::Json) => serde_json::to_string(&Envelope { schema, record: entry, slow_outlier: outlier, extracted, geo }).unwrap(),
+            Some(OutputFormat::JsonPretty) => serde_json::to_string_pretty(&Envelope { schema, record: entry, slow_outlier: outlier, extracted, geo }).unwrap(),
+            Some(OutputFormat::KvJson) => output_kv_json(entry),
+            Some(OutputFormat::Yaml) => {
+                format!("---\n{}",
+                    serde_yaml::to_string(&Envelope { schema, record: entry, slow_outlier: outlier, extracted, geo }).unwrap()
+                )
+            }
+            Some(OutputFormat::Wide) => {
+                let table = match args.table_fields.is_empty() {
+                    true => output_table(entry, &theme).unwrap(),
+                    false => output_table_fields(entry, &theme, &args.table_fields),
+                };
+                table + &geo.map_or("".to_string(), |geo| output_geo(&geo, &theme))
+            }
+            Some(OutputFormat::Influx) => output_influx(entry, args.utc_offset),
+            Some(OutputFormat::Clickhouse) => output_clickhouse(entry),
+            Some(OutputFormat::Csv) => output_csv(entry, &args.fields),
+            Some(OutputFormat::Logfmt) => output_logfmt(entry),
+            Some(OutputFormat::Template) => template.as_ref().map_or("".to_string(), |template| template.render(entry)),
+            Some(OutputFormat::Columns) => columns_row(&ascii_table_row(entry), &column_widths, table_theme),
+            Some(OutputFormat::Parquet) | Some(OutputFormat::AsciiTable) | Some(OutputFormat::Html) | Some(OutputFormat::SummaryJson) => unreachable!(),
+            Some(OutputFormat::Color) | None if !args.color() => entry.colorless_with(separator),
+            Some(OutputFormat::Color) | None => {
+                let colorized = entry.colorize_themed(&theme, args.color_by_name);
+                match anomalous || outlier == Some(true) {
+                    true => format!("{} {}", "⚠".red().bold(), colorized),
+                    false => colorized
                 }
             }
+        }
+    };
+
+        // Returns what to print for the line, so the caller decides the order it comes out in
+        let parser = |(name, line): (Arc<str>, String)| -> Vec<String> {
+            let named = |formatted: String| match args.with_filename {
+                true => format!("{}:{}", name, formatted),
+                false => formatted
+            };
+
+            // Context needs the entries around a match, so they can't be skipped unparsed
+            if context.is_none() && !filter.matches_line(&line) {
+                line_counts.count(&line_counts.skipped);
+                return vec![]
+            }
 
-            match HaproxyLogEntry::parse(&line) {
-                Ok(entry) => {
-                    if args.errors && !entry.is_error() {
-                        return
+            let mapped = filter.map_line(&line);
+            match filter.parse(&line, &mapped) {
+                Ok(mut entry) => {
+                    line_counts.count(&line_counts.parsed);
+                    if args.parse_query {
+                        entry.parse_query();
+                    }
+                    if args.strip_query {
+                        entry.strip_query();
+                    }
+
+                    if args.verbose && (args.since.is_some() || args.until.is_some()) && entry.accepted_at.is_none() {
+                        eprintln!("Dropping entry with an unparseable accept date: {}", line);
+                    }
+
+                    if !filter.matches(&entry) {
+                        return match &context {
+                            Some(context) => context.lock().unwrap().other(named(format_entry(&entry, false, None))).into_iter().collect(),
+                            None => vec![]
+                        }
+                    }
+
+                    if args.verbose && entry.month_number(args.locale).is_none() {
+                        eprintln!("Unknown month {}, the syslog locale can be set with --locale: {}", entry.month, line);
                     }
 
-                    if args.terminations && !entry.termination_state.is_error() {
-                        return
+                    if let Some(server) = &server {
+                        server.broadcast(match args.fields.is_empty() {
+                            true => serde_json::to_string(&entry).unwrap(),
+                            false => serde_json::to_string(&project(&entry, &args.fields)).unwrap(),
+                        });
+                        return vec![]
                     }
 
-                    println!("{}", match args.output {
-                        Some(OutputFormat::Raw) => entry.colorless(),
-                        Some(OutputFormat::Json) => serde_json::to_string(&entry).unwrap(),
-                        Some(OutputFormat::Yaml) => { 
-                            format!("---\n{}",
-                                serde_yaml::to_string(&entry).unwrap()
-                            )
+                    if let Some(sink) = &parquet {
+                        if let Err(e) = sink.lock().unwrap().push(&entry) {
+                            eprintln!("Failed to write parquet: {}", e);
                         }
-                        Some(OutputFormat::Wide) => output_table(&entry).unwrap(),
-                        Some(OutputFormat::Color) | None => entry.colorize()
-                    });
+                        return vec![]
+                    }
+
+                    if matches!(args.output, Some(OutputFormat::AsciiTable)) || (matches!(args.output, Some(OutputFormat::Columns)) && !stream_columns) {
+                        table_rows.lock().unwrap().push(ascii_table_row(&entry));
+                        return vec![]
+                    }
+                    if matches!(args.output, Some(OutputFormat::Html)) {
+                        table_rows.lock().unwrap().push(html_table_row(&entry));
+                        return vec![]
+                    }
+
+                    let anomalous = detector.as_ref().is_some_and(|d| d.observe(entry.timers.total));
+                    let outlier = outliers.as_ref().map(|limits| limits.is_outlier(&entry));
+                    let formatted = named(format_entry(&entry, anomalous, outlier));
+                    match &context {
+                        Some(context) => context.lock().unwrap().matched(formatted),
+                        None => vec![formatted],
+                    }
                 }
-                Err(_) => {
+                Err(e) => {
+                    line_counts.count(&line_counts.failed);
                     if args.verbose {
-                        eprintln!("Failed to parse line: {}", line);
+                        eprintln!("Failed to parse line ({}): {}", e, line);
+                    }
+                    if let Some(failures) = &failures {
+                        let failure = serde_json::json!({ "error": e.kind(), "message": e.to_string(), "line": line });
+                        if let Err(e) = writeln!(failures.lock().unwrap(), "{}", failure) {
+                            eprintln!("Failed to write failure: {}", e);
+                        }
                     }
+                    vec![]
                 }
             }
     };
 
-    match reader {
-        Reader::File(reader) => {
-            if args.serial {
-                reader.lines().filter_map(|line| line.ok()).for_each(parser);
-            } else {
-                reader.lines().par_bridge().filter_map(|line| line.ok()).for_each(parser);
-            }
-        }
-        Reader::Stdin(reader) => {
-            if args.serial {
-                reader.lines().filter_map(|line| line.ok()).for_each(parser);
-            } else {
-                reader.lines().par_bridge().filter_map(|line| line.ok()).for_each(parser);
-            }
+    // Before any worker can print a row
+    if let Some(OutputFormat::Csv) = args.output {
+        println!("{}", csv_header(&args.fields));
+    }
+    if stream_columns {
+        println!("{}", columns_header(&column_widths, table_theme));
+    }
+
+    let print = |lines: Vec<String>| {
+        for line in lines {
+            println!("{}", line);
         }
-    
+    };
+    let lines = inputs.named_lines();
+    if args.serial {
+        lines.map(parser).for_each(print);
+    } else if args.ordered {
+        let reorder = Reorder::new(REORDER_CAPACITY);
+        lines.zip(0..)
+            .map(|(line, index)| {
+                reorder.wait_for_room(index);
+                (line, index)
+            })
+            .par_bridge()
+            .for_each(|(line, index)| reorder.done(index, parser(line)));
+    } else {
+        lines.par_bridge().map(parser).for_each(print);
     }
+
+    match args.output {
+        Some(OutputFormat::AsciiTable) => print!("{}", output_ascii_table(&table_rows.into_inner().unwrap(), table_theme)),
+        Some(OutputFormat::Columns) if !stream_columns => print!("{}", output_columns(&table_rows.into_inner().unwrap(), table_theme)),
+        Some(OutputFormat::Html) => print!("{}", output_html(&table_rows.into_inner().unwrap())),
+        _ => {}
+    }
+
+    if let Some(sink) = parquet {
+        sink.into_inner().unwrap().finish()?;
+    }
+
+    if let Some(failures) = failures {
+        failures.into_inner().unwrap().flush()?;
+    }
+
+    line_counts.report(args.quiet);
     Ok(())
 }
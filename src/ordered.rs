@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex};
+
+// How many lines may finish ahead of the oldest unprinted one before reading waits for it
+pub const REORDER_CAPACITY: u64 = 4096;
+
+struct State {
+    // Index of the next line to print
+    next: u64,
+    // Output of lines that finished before an earlier one did
+    pending: BTreeMap<u64, Vec<String>>,
+}
+
+// Prints what the parallel workers made of each input line in input order
+pub struct Reorder {
+    state: Mutex<State>,
+    room: Condvar,
+    capacity: u64,
+}
+
+impl Reorder {
+    pub fn new(capacity: u64) -> Reorder {
+        Reorder {
+            state: Mutex::new(State { next: 0, pending: BTreeMap::new() }),
+            room: Condvar::new(),
+            capacity,
+        }
+    }
+
+    // Called before handing out a line, blocks while it would be too far ahead of the next
+    // one to print. Every earlier line is already with a worker, so this always wakes up
+    pub fn wait_for_room(&self, index: u64) {
+        let mut state = self.state.lock().unwrap();
+        while index >= state.next + self.capacity {
+            state = self.room.wait(state).unwrap();
+        }
+    }
+
+    // Every handed out line has to come back through here, even when it printed nothing
+    pub fn done(&self, index: u64, lines: Vec<String>) {
+        let mut state = self.state.lock().unwrap();
+        state.pending.insert(index, lines);
+        let mut advanced = false;
+        loop {
+            let next = state.next;
+            match state.pending.remove(&next) {
+                Some(lines) => {
+                    for line in lines {
+                        println!("{}", line);
+                    }
+                    state.next += 1;
+                    advanced = true;
+                }
+                None => break,
+            }
+        }
+        if advanced {
+            self.room.notify_all();
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+use l8r::HaproxyLogEntry;
+
+// Backends with fewer entries than this have no baseline, so none of their entries are flagged
+const MIN_SAMPLES: u64 = 10;
+
+#[derive(Debug, Default)]
+struct Moments {
+    count: u64,
+    sum: f64,
+    sum_sq: f64,
+}
+
+// The total times of every backend, gathered in a first pass over the input
+#[derive(Debug, Default)]
+pub struct Baselines {
+    backends: HashMap<String, Moments>,
+}
+
+impl Baselines {
+    pub fn add(&mut self, entry: &HaproxyLogEntry) {
+        let moments = self.backends.entry(entry.backend_name.to_string()).or_default();
+        let total = entry.timers.total as f64;
+        moments.count += 1;
+        moments.sum += total;
+        moments.sum_sq += total * total;
+    }
+
+    pub fn merge(mut self, other: Baselines) -> Baselines {
+        for (backend, other) in other.backends {
+            let moments = self.backends.entry(backend).or_default();
+            moments.count += other.count;
+            moments.sum += other.sum;
+            moments.sum_sq += other.sum_sq;
+        }
+        self
+    }
+
+    // Mean plus `k` standard deviations of every backend with enough entries
+    pub fn limits(&self, k: f64) -> OutlierLimits {
+        OutlierLimits {
+            backends: self.backends.iter()
+                .filter(|(_, m)| m.count >= MIN_SAMPLES)
+                .map(|(backend, m)| {
+                    let mean = m.sum / m.count as f64;
+                    let variance = (m.sum_sq / m.count as f64 - mean * mean).max(0.0);
+                    (backend.clone(), mean + k * variance.sqrt())
+                })
+                .collect(),
+        }
+    }
+}
+
+pub struct OutlierLimits {
+    backends: HashMap<String, f64>,
+}
+
+impl OutlierLimits {
+    pub fn is_outlier(&self, entry: &HaproxyLogEntry) -> bool {
+        self.backends.get(entry.backend_name).is_some_and(|limit| entry.timers.total as f64 > *limit)
+    }
+}
@@ -0,0 +1,519 @@
+use std::collections::BTreeMap;
+use chrono::FixedOffset;
+use colored::Colorize;
+use serde::ser::{Serialize, SerializeMap};
+use l8r::{HaproxyLogEntry, Theme};
+use crate::geoip::Geo;
+use l8r::haproxy::{canonical_field_name, StatusClass};
+
+
+// Tag keys and values may not contain unescaped commas, equals signs or spaces
+fn escape_influx_tag(s: &str) -> String {
+    s.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+// InfluxDB line protocol, with the accept date taken as local time at `utc_offset`
+pub fn output_influx(entry: &HaproxyLogEntry, utc_offset: FixedOffset) -> String {
+    let mut tags = vec![
+        ("frontend", entry.frontend_name.to_string()),
+        ("backend", entry.backend_name.to_string()),
+        ("server", entry.server_name.to_string()),
+    ];
+    if let Some(code) = entry.response_code {
+        tags.push(("status", code.to_string()));
+    }
+    tags.push(("status_class", entry.status_class().to_string()));
+    let tags = tags
+    .iter()
+    .map(|(key, value)| format!("{}={}", key, escape_influx_tag(value)))
+    .collect::<Vec<String>>()
+    .join(",");
+
+    let timers = [
+        ("tq", entry.timers.client_request),
+        ("tw", entry.timers.queue_wait),
+        ("tc", entry.timers.establish),
+        ("tr", entry.timers.server_response),
+        ("tt", Some(entry.timers.total)),
+    ];
+    let mut fields = timers.iter()
+        .filter_map(|(key, value)| value.map(|value| format!("{}={}i", key, value)))
+        .collect::<Vec<String>>()
+        .join(",");
+    if let Some(bytes) = entry.bytes_read {
+        fields.push_str(&format!(",bytes_read={}i", bytes));
+    }
+
+    let timestamp = entry.accepted_at.and_then(|t| t.and_local_timezone(utc_offset).single()).and_then(|t| t.timestamp_nanos_opt());
+    match timestamp {
+        Some(ns) => format!("haproxy,{} {} {}", tags, fields, ns),
+        None => format!("haproxy,{} {}", tags, fields),
+    }
+}
+
+// The table `--output clickhouse` rows are meant for, in column order
+pub const CLICKHOUSE_SCHEMA: &str = "CREATE TABLE haproxy_logs
+(
+    accepted_at Nullable(DateTime64(3)),
+    host String,
+    program String,
+    pid Nullable(UInt64),
+    source_ip_port String,
+    source_ip String,
+    source_port UInt16,
+    frontend_name String,
+    backend_name String,
+    server_name String,
+    timers_client_request Nullable(UInt64),
+    timers_queue_wait Nullable(UInt64),
+    timers_establish Nullable(UInt64),
+    timers_server_response Nullable(UInt64),
+    timers_total UInt64,
+    response_code Nullable(UInt16),
+    bytes_read Nullable(UInt64),
+    termination_state LowCardinality(String),
+    conn_counts_current UInt64,
+    conn_counts_limit UInt64,
+    conn_counts_max UInt64,
+    conn_counts_total UInt64,
+    conn_counts_rejected UInt64,
+    queue_server UInt64,
+    queue_backend UInt64,
+    request Nullable(String)
+)
+ENGINE = MergeTree
+ORDER BY (backend_name, accepted_at)
+SETTINGS allow_nullable_key = 1";
+
+// The escapes ClickHouse's TSV format reads back, so no control character in a value can break its row
+fn escape_tsv(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r").replace('\0', "\\0")
+}
+
+// One row of ClickHouse's TSV format, for `INSERT INTO haproxy_logs FORMAT TSV`
+pub fn output_clickhouse(entry: &HaproxyLogEntry) -> String {
+    let null = "\\N".to_string();
+    [
+        entry.accepted_at.map_or(null.clone(), |t| t.format("%Y-%m-%d %H:%M:%S%.3f").to_string()),
+        escape_tsv(entry.host),
+        escape_tsv(entry.program),
+        entry.pid.map_or(null.clone(), |pid| pid.to_string()),
+        escape_tsv(entry.source.raw),
+        escape_tsv(entry.source.ip),
+        entry.source.port.to_string(),
+        escape_tsv(entry.frontend_name),
+        escape_tsv(entry.backend_name),
+        escape_tsv(entry.server_name),
+        entry.timers.client_request.map_or(null.clone(), |t| t.to_string()),
+        entry.timers.queue_wait.map_or(null.clone(), |t| t.to_string()),
+        entry.timers.establish.map_or(null.clone(), |t| t.to_string()),
+        entry.timers.server_response.map_or(null.clone(), |t| t.to_string()),
+        entry.timers.total.to_string(),
+        entry.response_code.map_or(null.clone(), |code| code.to_string()),
+        entry.bytes_read.map_or(null.clone(), |bytes| bytes.to_string()),
+        escape_tsv(&entry.termination_state.to_string()),
+        entry.conn_counts.current.to_string(),
+        entry.conn_counts.limit.to_string(),
+        entry.conn_counts.max.to_string(),
+        entry.conn_counts.total.to_string(),
+        entry.conn_counts.rejected.to_string(),
+        entry.queue.server.to_string(),
+        entry.queue.backend.to_string(),
+        entry.request.as_ref().map_or(null, |request| escape_tsv(&request.raw)),
+    ].join("\t")
+}
+
+// Every scalar field, by its `HaproxyLogEntry::field` name
+const CSV_FIELDS: &[&str] = &[
+    "month", "day", "time", "host", "program", "pid", "source_ip", "source_port", "time_stamp_accepted", "accepted_at",
+    "frontend_name", "backend_name", "server_name",
+    "timers.client_request", "timers.queue_wait", "timers.establish", "timers.server_response", "timers.total",
+    "response_code", "bytes_read", "termination_state",
+    "conn_counts.current", "conn_counts.limit", "conn_counts.max", "conn_counts.total", "conn_counts.rejected",
+    "retried", "redispatched", "queue.server", "queue.backend",
+    "format", "request.method", "request.path", "request.query", "request.protocol", "request",
+];
+
+// Quoted only when needed, as RFC 4180 allows
+fn escape_csv(s: &str) -> String {
+    match s.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_string(),
+    }
+}
+
+// The `--fields` given, or every scalar field, with underscores for the dots of nested ones
+pub fn csv_header(fields: &[String]) -> String {
+    match fields {
+        [] => CSV_FIELDS.iter().map(|field| field.replace('.', "_")).collect::<Vec<String>>().join(","),
+        fields => fields.iter().map(|field| escape_csv(&field.replace('.', "_"))).collect::<Vec<String>>().join(","),
+    }
+}
+
+pub fn output_csv(entry: &HaproxyLogEntry, fields: &[String]) -> String {
+    if !fields.is_empty() {
+        return project(entry, fields).0.iter()
+            .map(|(_, value)| match value {
+                serde_json::Value::Null => "".to_string(),
+                serde_json::Value::String(s) => escape_csv(s),
+                value => escape_csv(&value.to_string()),
+            })
+            .collect::<Vec<String>>()
+            .join(",");
+    }
+
+    CSV_FIELDS.iter()
+        .map(|field| escape_csv(&entry.field(field).unwrap_or_default()))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+// Bumped whenever a serialized field is renamed, removed or changes type
+pub const SCHEMA_VERSION: u32 = 1;
+
+// A record with `_schema` in front of its own fields when there is a version to report,
+// whether it's a slow outlier when those are flagged and the fields taken out with --extract
+// and --geoip
+#[derive(serde::Serialize)]
+pub struct Envelope<'a, T: Serialize> {
+    #[serde(rename = "_schema", skip_serializing_if = "Option::is_none")]
+    pub schema: Option<u32>,
+    #[serde(flatten)]
+    pub record: &'a T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slow_outlier: Option<bool>,
+    #[serde(flatten)]
+    pub extracted: BTreeMap<String, &'a str>,
+    #[serde(flatten)]
+    pub geo: Option<Geo>,
+}
+
+// Serializes as a map that keeps the order the fields were given in
+pub struct Projection(Vec<(String, serde_json::Value)>);
+
+impl Serialize for Projection {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+// The named fields, by their dotted path in the serialized entry
+pub fn project(entry: &HaproxyLogEntry, fields: &[String]) -> Projection {
+    let value = serde_json::to_value(entry).unwrap();
+    Projection(fields.iter().map(|name| {
+        let path = canonical_field_name(name).unwrap_or(name);
+        let projected = match path.split('.').try_fold(&value, |v, key| v.get(key)) {
+            Some(v) => v.clone(),
+            // Derived fields like status_class aren't part of the serialized entry
+            None => entry.field(name).map_or(serde_json::Value::Null, serde_json::Value::String),
+        };
+        (name.clone(), projected)
+    }).collect())
+}
+
+fn escape_logfmt(s: &str) -> String {
+    match s.is_empty() || s.contains([' ', '=', '"', '\\']) {
+        true => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => s.to_string(),
+    }
+}
+
+// Loki style key=value pairs, leaving out whatever the entry didn't log
+pub fn output_logfmt(entry: &HaproxyLogEntry) -> String {
+    let mut pairs = vec![
+        ("time", Some(entry.time_stamp_accepted.to_string())),
+        ("host", Some(entry.host.to_string())),
+        ("client", Some(entry.source.raw.to_string())),
+        ("frontend", Some(entry.frontend_name.to_string())),
+        ("backend", Some(entry.backend_name.to_string())),
+        ("server", Some(entry.server_name.to_string())),
+        ("tq_ms", entry.timers.client_request.map(|t| t.to_string())),
+        ("tw_ms", entry.timers.queue_wait.map(|t| t.to_string())),
+        ("tc_ms", entry.timers.establish.map(|t| t.to_string())),
+        ("tr_ms", entry.timers.server_response.map(|t| t.to_string())),
+        ("total_ms", Some(entry.timers.total.to_string())),
+        ("response_code", entry.response_code.map(|code| code.to_string())),
+        ("bytes_read", entry.bytes_read.map(|bytes| bytes.to_string())),
+        ("term", Some(entry.termination_state.to_string())),
+        ("retries", entry.retries.map(|retries| retries.to_string())),
+        ("queue", Some(entry.queue.to_string())),
+    ];
+    if let Some(request) = &entry.request {
+        pairs.extend([
+            ("method", request.method.map(|m| m.to_string())),
+            ("path", request.path.as_ref().map(|p| p.to_string())),
+            ("request", Some(request.raw.to_string())),
+        ]);
+    }
+
+    pairs.iter()
+        .filter_map(|(key, value)| value.as_ref().map(|value| format!("{}={}", key, escape_logfmt(value))))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub const ASCII_TABLE_HEADER: &[&str] = &["Time", "Frontend", "Backend", "Server", "Timers", "Status", "Bytes", "Term", "Request"];
+const ASCII_TABLE_REQUEST_WIDTH: usize = 60;
+
+pub fn ascii_table_row(entry: &HaproxyLogEntry) -> Vec<String> {
+    let request = match entry.request_text().chars().count() > ASCII_TABLE_REQUEST_WIDTH {
+        true => format!("{}…", entry.request_text().chars().take(ASCII_TABLE_REQUEST_WIDTH - 1).collect::<String>()),
+        false => entry.request_text().to_string(),
+    };
+
+    vec![
+        entry.time_stamp_accepted.to_string(),
+        entry.frontend_name.to_string(),
+        entry.backend_name.to_string(),
+        entry.server_name.to_string(),
+        entry.timers.to_string(),
+        entry.response_code_raw.unwrap_or("-").to_string(),
+        entry.bytes_read_raw.to_string(),
+        entry.termination_state.to_string(),
+        request,
+    ]
+}
+
+// Same scheme as `HaproxyLogEntry::colorize_themed`, applied to an already padded cell
+fn color_cell(column: usize, cell: &str, padded: String, theme: &Theme) -> String {
+    let color = match ASCII_TABLE_HEADER[column] {
+        "Frontend" => theme.frontend,
+        "Backend" => theme.backend,
+        "Server" => theme.server,
+        "Status" => match cell.parse() {
+            Ok(code) => theme.status(StatusClass::from_code(code)),
+            Err(_) => theme.text,
+        },
+        "Term" => match cell.chars().all(|c| c == '-') {
+            true => theme.termination_normal,
+            false => theme.termination_error,
+        },
+        _ => theme.text,
+    };
+    padded.color(color).to_string()
+}
+
+// Colorless without a theme
+pub fn output_ascii_table(rows: &[Vec<String>], theme: Option<&Theme>) -> String {
+    let mut widths: Vec<usize> = ASCII_TABLE_HEADER.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let border = |left: &str, middle: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+        format!("{}{}{}\n", left, segments.join(middle), right)
+    };
+    let line = |cells: Vec<String>| format!("│ {} │\n", cells.join(" │ "));
+    let pad = |cell: &str, width: usize| format!("{}{}", cell, " ".repeat(width - cell.chars().count()));
+
+    let mut result = border("┌", "┬", "┐");
+    result.push_str(&line(ASCII_TABLE_HEADER.iter().zip(&widths).map(|(h, w)| {
+        if theme.is_some() { pad(h, *w).bold().to_string() } else { pad(h, *w) }
+    }).collect()));
+    result.push_str(&border("├", "┼", "┤"));
+    for row in rows {
+        result.push_str(&line(row.iter().zip(&widths).enumerate().map(|(column, (cell, w))| {
+            match theme {
+                Some(theme) => color_cell(column, cell, pad(cell, *w), theme),
+                None => pad(cell, *w),
+            }
+        }).collect()));
+    }
+    result.push_str(&border("└", "┴", "┘"));
+    result
+}
+
+// `--output columns` widths for streams, which can't wait for the widest cell. The request
+// is last and never padded
+const COLUMN_WIDTHS: [usize; 9] = [24, 12, 16, 12, 18, 6, 8, 4, 0];
+
+pub fn fixed_column_widths() -> Vec<usize> {
+    COLUMN_WIDTHS.to_vec()
+}
+
+fn columns_line(cells: &[String], widths: &[usize], style: impl Fn(usize, &str, String) -> String) -> String {
+    let last = cells.len().saturating_sub(1);
+    cells.iter().zip(widths).enumerate().map(|(column, (cell, width))| {
+        let cell = match cell.chars().count() > *width && column != last {
+            true => format!("{}…", cell.chars().take(width - 1).collect::<String>()),
+            false => cell.clone(),
+        };
+        let padded = match column == last {
+            true => cell.clone(),
+            false => format!("{}{}", cell, " ".repeat(width - cell.chars().count())),
+        };
+        style(column, &cell, padded)
+    }).collect::<Vec<String>>().join("  ")
+}
+
+pub fn columns_header(widths: &[usize], theme: Option<&Theme>) -> String {
+    let header: Vec<String> = ASCII_TABLE_HEADER.iter().map(|h| h.to_string()).collect();
+    columns_line(&header, widths, |_, _, padded| match theme {
+        Some(_) => padded.bold().to_string(),
+        None => padded,
+    })
+}
+
+// Cells that don't fit their width are cut short
+pub fn columns_row(row: &[String], widths: &[usize], theme: Option<&Theme>) -> String {
+    columns_line(row, widths, |column, cell, padded| match theme {
+        Some(theme) => color_cell(column, cell, padded, theme),
+        None => padded,
+    })
+}
+
+// Every column as wide as its widest cell
+pub fn output_columns(rows: &[Vec<String>], theme: Option<&Theme>) -> String {
+    let mut widths: Vec<usize> = ASCII_TABLE_HEADER.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut result = format!("{}\n", columns_header(&widths, theme));
+    for row in rows {
+        result.push_str(&format!("{}\n", columns_row(row, &widths, theme)));
+    }
+    result
+}
+
+// The request in full, a browser wraps what a terminal would have to cut
+pub fn html_table_row(entry: &HaproxyLogEntry) -> Vec<String> {
+    let mut row = ascii_table_row(entry);
+    if let Some(request) = row.last_mut() {
+        *request = entry.request_text().to_string();
+    }
+    row
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+// Status and termination colors follow `Theme::status` and `Theme::termination` of the default theme
+const HTML_STYLE: &str = "body { font-family: sans-serif; }
+table { border-collapse: collapse; font-family: monospace; font-size: 13px; }
+th, td { border: 1px solid #ccc; padding: 2px 6px; text-align: left; vertical-align: top; }
+th { background: #eee; }
+td.request { word-break: break-all; }
+td.s2xx { color: #2e7d32; }
+td.s3xx { color: #b8860b; }
+td.s4xx, td.s5xx, td.term-error { color: #c62828; font-weight: bold; }
+tr.s5xx { background: #fdecea; }";
+
+// A whole page, so it can be attached or opened as it is
+pub fn output_html(rows: &[Vec<String>]) -> String {
+    let mut result = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>l8r</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<table>\n", HTML_STYLE);
+    result.push_str(&format!("<tr>{}</tr>\n", ASCII_TABLE_HEADER.iter().map(|h| format!("<th>{}</th>", h)).collect::<String>()));
+    let status_column = ASCII_TABLE_HEADER.iter().position(|h| *h == "Status");
+    for row in rows {
+        let status = status_column.and_then(|column| row.get(column))
+            .and_then(|cell| cell.parse().ok())
+            .map_or("".to_string(), |code| format!(" class=\"s{}\"", StatusClass::from_code(code)));
+        let cells: String = row.iter().enumerate().map(|(column, cell)| {
+            let class = match ASCII_TABLE_HEADER[column] {
+                "Status" => status.as_str(),
+                "Term" if !cell.chars().all(|c| c == '-') => " class=\"term-error\"",
+                "Request" => " class=\"request\"",
+                _ => "",
+            };
+            format!("<td{}>{}</td>", class, escape_html(cell))
+        }).collect();
+        result.push_str(&format!("<tr{}>{}</tr>\n", status, cells));
+    }
+    result.push_str("</table>\n</body>\n</html>\n");
+    result
+}
+
+// The first clause of a termination reason, the full descriptions are too long for one line
+fn short_description(description: &str) -> &str {
+    let end = [", ", " (", "."].iter().filter_map(|sep| description.find(sep)).min().unwrap_or(description.len());
+    &description[..end]
+}
+
+// One line per error for `--tail-errors`: when, where, what and how long
+pub fn output_compact(entry: &HaproxyLogEntry, theme: &Theme) -> String {
+    let reason = match entry.termination_state.is_error() {
+        true => format!(" {}", short_description(&entry.termination_state.termination_reason.description)),
+        false => "".to_string(),
+    };
+    let code = entry.response_code_raw.unwrap_or("-").color(theme.status(entry.status_class()));
+
+    format!("{} {}/{} {} {} {}ms{} {}",
+        entry.time_stamp_accepted.color(theme.text),
+        entry.backend_name.color(theme.backend),
+        entry.server_name.color(theme.server),
+        match entry.status_class() {
+            StatusClass::ServerError => code.bold(),
+            _ => code,
+        },
+        entry.termination_state.to_string().color(theme.termination(&entry.termination_state)),
+        entry.timers.total.to_string().color(theme.text),
+        reason.color(theme.termination_error),
+        entry.request_text().color(theme.text)
+    )
+}
+
+// Hoists nested objects into their parent with the keys joined by underscores
+fn flatten_into(prefix: &str, value: serde_json::Value, out: &mut serde_json::Map<String, serde_json::Value>) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, value) in fields {
+                let key = match prefix {
+                    "" => key,
+                    _ => format!("{}_{}", prefix, key),
+                };
+                flatten_into(&key, value, out);
+            }
+        }
+        value => {
+            out.insert(prefix.to_string(), value);
+        }
+    }
+}
+
+// Single-level JSON for ingestion systems that map nested objects badly
+pub fn output_kv_json(entry: &HaproxyLogEntry) -> String {
+    let mut flat = serde_json::Map::new();
+    flatten_into("", serde_json::to_value(entry).unwrap(), &mut flat);
+    serde_json::to_string(&flat).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = include_str!("../tests/fixtures/httplog.log");
+
+    #[test]
+    fn influx_timestamp_at_utc_offset() {
+        let entry = HaproxyLogEntry::parse(LINE).unwrap();
+        let utc = output_influx(&entry, "+00:00".parse().unwrap());
+        let local = output_influx(&entry, "+02:00".parse().unwrap());
+        assert!(utc.ends_with(" 1715126910660000000"), "{}", utc);
+        assert!(local.ends_with(" 1715119710660000000"), "{}", local);
+    }
+
+    #[test]
+    fn csv_header_names() {
+        assert!(csv_header(&[]).contains(",timers_server_response,"));
+        let fields = ["timers.server_response".to_string(), "status".to_string()];
+        assert_eq!(csv_header(&fields), "timers_server_response,status");
+    }
+
+    #[test]
+    fn tsv_escapes() {
+        assert_eq!(escape_tsv("a\\b\tc\nd\re\0f"), "a\\\\b\\tc\\nd\\re\\0f");
+        let line = LINE.replace("/index.html", "/a\rb");
+        let entry = HaproxyLogEntry::parse(&line).unwrap();
+        assert!(output_clickhouse(&entry).ends_with("\tGET /a\\rb HTTP/1.1"));
+    }
+}
@@ -0,0 +1,106 @@
+use std::borrow::Cow;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use l8r::haproxy::{RE, TCP_RE};
+use l8r::LogFormat;
+
+// HAProxy's `option httplog clf`, with the syslog prefix. The accept milliseconds and the
+// timers, connection counts and queues all come as separate fields after the request, which
+// ends at the first quote followed by the status and bytes so it can contain quotes itself
+static CLF_RE: Lazy<Regex> = regex_static::lazy_regex!(r#"^(?P<syslog>\p{L}{3,5}\.?\s+\d{1,2}\s+[0-9:]{8}\s+[\w.-]+\s+[\w.-]+(?:\[\d+\])?:)\s+"?(?P<ci>[0-9A-Fa-f:.]+)"?\s+-\s+-\s+\[(?P<date>[^\]\s]+)(?:\s+[+-]\d{4})?\]\s+"(?P<request>.*?)"\s+(?P<status>\d+)\s+(?P<bytes>\+?\d+|-)\s+"[^"]*"\s+"[^"]*"\s+(?P<cp>\d+)\s+(?P<ms>\d+)\s+"?(?P<ft>[^"\s]+)"?\s+"?(?P<b>[^"\s]+)"?\s+"?(?P<s>[^"\s]+)"?\s+(?P<tq>-?\d+)\s+(?P<tw>-?\d+)\s+(?P<tc>-?\d+)\s+(?P<tr>-?\d+)\s+(?P<tt>\+?\d+)\s+(?P<tsc>[-A-Za-z]{4})\s+(?P<ac>\d+)\s+(?P<fc>\d+)\s+(?P<bc>\d+)\s+(?P<sc>\d+)\s+(?P<rc>\+?\d+)\s+(?P<sq>\d+)\s+(?P<bq>\d+)(?:\s.*)?$"#);
+
+// Where every CLF field goes in an httplog line
+const CLF_MAPPING: &str = r#"${syslog} ${ci}:${cp} [${date}.${ms}] ${ft} ${b}/${s} ${tq}/${tw}/${tc}/${tr}/${tt} ${status} ${bytes} - - ${tsc} ${ac}/${fc}/${bc}/${sc}/${rc} ${sq}/${bq} "${request}""#;
+
+// The log formats HAProxy ships with, as `--preset` names them
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Preset {
+    Httplog,
+    Tcplog,
+    Clf,
+}
+
+impl Preset {
+    pub fn regex(&self) -> &'static Regex {
+        match self {
+            Preset::Httplog => &RE,
+            Preset::Tcplog => &TCP_RE,
+            Preset::Clf => &CLF_RE,
+        }
+    }
+
+    // The format lines are parsed in, after `mapping` when there is one
+    pub fn format(&self) -> LogFormat {
+        match self {
+            Preset::Httplog | Preset::Clf => LogFormat::Http,
+            Preset::Tcplog => LogFormat::Tcp,
+        }
+    }
+
+    // A replacement template that turns a line matching `regex` into one in `format`,
+    // for formats whose fields don't line up with an existing one
+    pub fn mapping(&self) -> Option<&'static str> {
+        match self {
+            Preset::Httplog | Preset::Tcplog => None,
+            Preset::Clf => Some(CLF_MAPPING),
+        }
+    }
+
+    // Lines that don't match are left alone and fail to parse later on
+    pub fn map_line<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        match self.mapping() {
+            Some(mapping) => self.regex().replace(line, mapping),
+            None => Cow::Borrowed(line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use l8r::HaproxyLogEntry;
+
+    fn clf_line(request: &str) -> String {
+        format!(r#"Feb  6 12:12:56 localhost haproxy[14387]: 10.0.1.2 - - [06/Feb/2009:12:12:51 +0100] "{}" 200 2750 "" "" 33317 661 "httpin" "static" "srv1" 10 0 30 69 109 ---- 1 1 1 1 0 0 0 "" "" "" """#, request)
+    }
+
+    fn assert_request(request: &str) {
+        let line = clf_line(request);
+        let captures = CLF_RE.captures(&line).unwrap();
+        assert_eq!(&captures["request"], request);
+        assert_eq!(&captures["status"], "200");
+        assert_eq!(&captures["bytes"], "2750");
+
+        let mapped = Preset::Clf.map_line(&line);
+        let entry = HaproxyLogEntry::parse_as(&mapped, Preset::Clf.format()).unwrap();
+        assert_eq!(entry.request.unwrap().raw, request);
+        assert_eq!(entry.response_code, Some(200));
+        assert_eq!(entry.bytes_read, Some(2750));
+    }
+
+    #[test]
+    fn clf_plain_request() {
+        assert_request("GET /index.html HTTP/1.1");
+    }
+
+    #[test]
+    fn clf_request_with_embedded_quote() {
+        assert_request(r#"GET /search?q="x" HTTP/1.1"#);
+    }
+
+    #[test]
+    fn clf_request_with_escaped_quote() {
+        assert_request(r#"GET /search?q=\"x\" HTTP/1.1"#);
+    }
+
+    #[test]
+    fn clf_request_with_trailing_quote() {
+        assert_request(r#"GET /a""#);
+    }
+
+    #[test]
+    fn clf_empty_request() {
+        assert_request("");
+    }
+}
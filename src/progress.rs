@@ -0,0 +1,202 @@
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const REFRESH: Duration = Duration::from_millis(100);
+const BAR_WIDTH: usize = 30;
+const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        unit => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
+
+// How far the readers are into the input files. The handles are duplicates of the readers' own,
+// which share their offset, so the position is the readers' whatever the parsing threads are at.
+// For gzip files that's the position in the compressed file
+pub struct Progress {
+    files: Vec<(File, u64)>,
+}
+
+impl Progress {
+    // Only regular files have a size to measure against
+    pub fn new(files: Vec<File>) -> Option<Progress> {
+        let files: Vec<(File, u64)> = files.into_iter()
+            .filter_map(|file| file.metadata().ok().filter(|m| m.is_file()).map(|m| (file, m.len())))
+            .collect();
+        match files.iter().map(|(_, size)| size).sum::<u64>() {
+            0 => None,
+            _ => Some(Progress { files }),
+        }
+    }
+
+    fn render(&mut self) -> String {
+        let total: u64 = self.files.iter().map(|(_, size)| size).sum();
+        let read: u64 = self.files.iter_mut()
+            .map(|(file, size)| file.stream_position().unwrap_or(0).min(*size))
+            .sum();
+        let fraction = read as f64 / total as f64;
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        format!("{}{} {:>3}% {} / {}", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled),
+            (fraction * 100.0) as u64, format_size(read), format_size(total))
+    }
+
+    // Draws on stderr until the returned bar is dropped, or not at all when stderr isn't a terminal
+    pub fn start(self) -> ProgressBar {
+        match atty::is(atty::Stream::Stderr) {
+            true => self.start_on(Box::new(std::io::stderr())),
+            false => ProgressBar { done: Arc::new(AtomicBool::new(true)), thread: None },
+        }
+    }
+
+    // Redraws the line on `target` until the bar is dropped, which clears it again
+    fn start_on(mut self, mut target: Box<dyn Write + Send>) -> ProgressBar {
+        let done = Arc::new(AtomicBool::new(false));
+        let stop = done.clone();
+        let thread = thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                write!(target, "\r{}", self.render()).ok();
+                target.flush().ok();
+                thread::park_timeout(REFRESH);
+            }
+            write!(target, "\r\x1b[2K").ok();
+            target.flush().ok();
+        });
+        ProgressBar { done, thread: Some(thread) }
+    }
+}
+
+pub struct ProgressBar {
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            thread.join().ok();
+        }
+    }
+}
+
+// Lines that keep the bar up until the last one has been read
+pub struct TrackedLines<I> {
+    lines: I,
+    bar: Option<ProgressBar>,
+}
+
+impl<I> TrackedLines<I> {
+    pub fn new(lines: I, bar: ProgressBar) -> TrackedLines<I> {
+        TrackedLines { lines, bar: Some(bar) }
+    }
+}
+
+impl<I: Iterator> Iterator for TrackedLines<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let line = self.lines.next();
+        if line.is_none() {
+            self.bar = None;
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::SeekFrom;
+    use std::sync::Mutex;
+
+    // A file of `size` bytes that's removed again once the test is done with it
+    struct Scratch(std::path::PathBuf);
+
+    impl Scratch {
+        fn new(name: &str, size: usize) -> (Scratch, File) {
+            let path = std::env::temp_dir().join(format!("l8r-progress-{}-{}", std::process::id(), name));
+            std::fs::write(&path, vec![b'x'; size]).unwrap();
+            let file = File::open(&path).unwrap();
+            (Scratch(path), file)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.0).ok();
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
+    #[test]
+    fn follows_the_reader_position() {
+        let (_scratch, mut reader) = Scratch::new("position", 2048);
+        let mut progress = Progress::new(vec![reader.try_clone().unwrap()]).unwrap();
+        assert_eq!(progress.render(), format!("{}   0% 0 B / 2.0 KB", "░".repeat(BAR_WIDTH)));
+
+        reader.seek(SeekFrom::Start(1024)).unwrap();
+        assert_eq!(progress.render(), format!("{}{}  50% 1.0 KB / 2.0 KB", "█".repeat(15), "░".repeat(15)));
+        reader.seek(SeekFrom::End(0)).unwrap();
+        assert!(progress.render().starts_with(&format!("{} 100%", "█".repeat(BAR_WIDTH))));
+    }
+
+    #[test]
+    fn adds_up_every_file() {
+        let (_first, first) = Scratch::new("first", 1024);
+        let (_second, second) = Scratch::new("second", 3072);
+        let mut progress = Progress::new(vec![first.try_clone().unwrap(), second]).unwrap();
+        (&first).seek(SeekFrom::End(0)).unwrap();
+        assert!(progress.render().ends_with(" 25% 1.0 KB / 4.0 KB"));
+    }
+
+    #[test]
+    fn nothing_to_measure() {
+        let (_scratch, empty) = Scratch::new("empty", 0);
+        assert!(Progress::new(vec![empty]).is_none());
+    }
+
+    #[test]
+    fn clears_the_line_when_done() {
+        let (_scratch, file) = Scratch::new("clear", 100);
+        let buffer = Buffer::default();
+        let lines = TrackedLines::new(0..3, Progress::new(vec![file]).unwrap().start_on(Box::new(buffer.clone())));
+        assert_eq!(lines.count(), 3);
+
+        let drawn = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(drawn.starts_with('\r'), "{:?}", drawn);
+        assert!(drawn.ends_with("\r\x1b[2K"), "{:?}", drawn);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use l8r::HaproxyLogEntry;
+use crate::histogram::DEFAULT_BUCKETS;
+
+#[derive(Debug, Default)]
+pub struct BackendMetrics {
+    pub requests: BTreeMap<String, u64>,
+    pub bytes_read: u64,
+    // Not cumulative yet, bucket i holds the totals at most DEFAULT_BUCKETS[i] and the last one the rest
+    pub buckets: Vec<u64>,
+    pub duration_sum: u64,
+}
+
+impl BackendMetrics {
+    fn add(&mut self, entry: &HaproxyLogEntry) {
+        let code = entry.response_code_raw.unwrap_or("-").to_string();
+        *self.requests.entry(code).or_insert(0) += 1;
+        self.bytes_read += entry.bytes_read.unwrap_or(0);
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; DEFAULT_BUCKETS.len() + 1];
+        }
+        self.buckets[DEFAULT_BUCKETS.partition_point(|&bound| bound < entry.timers.total)] += 1;
+        self.duration_sum += entry.timers.total;
+    }
+
+    fn merge(&mut self, other: BackendMetrics) {
+        for (code, count) in other.requests {
+            *self.requests.entry(code).or_insert(0) += count;
+        }
+        self.bytes_read += other.bytes_read;
+        if self.buckets.is_empty() {
+            self.buckets = other.buckets;
+        } else {
+            for (count, other) in self.buckets.iter_mut().zip(other.buckets) {
+                *count += other;
+            }
+        }
+        self.duration_sum += other.duration_sum;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub backends: BTreeMap<String, BackendMetrics>,
+}
+
+impl Metrics {
+    pub fn add(&mut self, entry: &HaproxyLogEntry) {
+        self.backends.entry(entry.backend_name.to_string()).or_default().add(entry);
+    }
+
+    pub fn merge(mut self, other: Metrics) -> Metrics {
+        for (backend, metrics) in other.backends {
+            self.backends.entry(backend).or_default().merge(metrics);
+        }
+        self
+    }
+}
+
+// Label values as the text exposition format wants them, with \, " and newlines escaped
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+pub fn output_prometheus(metrics: &Metrics) -> String {
+    let mut result = "".to_string();
+
+    result.push_str("# HELP l8r_requests_total Requests by backend and status code.\n");
+    result.push_str("# TYPE l8r_requests_total counter\n");
+    for (backend, backend_metrics) in &metrics.backends {
+        for (code, count) in &backend_metrics.requests {
+            result.push_str(&format!("l8r_requests_total{{backend=\"{}\",code=\"{}\"}} {}\n", escape_label(backend), escape_label(code), count));
+        }
+    }
+
+    result.push_str("# HELP l8r_bytes_read_total Bytes read by backend.\n");
+    result.push_str("# TYPE l8r_bytes_read_total counter\n");
+    for (backend, backend_metrics) in &metrics.backends {
+        result.push_str(&format!("l8r_bytes_read_total{{backend=\"{}\"}} {}\n", escape_label(backend), backend_metrics.bytes_read));
+    }
+
+    result.push_str("# HELP l8r_request_duration_ms Total time of requests by backend, in milliseconds.\n");
+    result.push_str("# TYPE l8r_request_duration_ms histogram\n");
+    for (backend, backend_metrics) in &metrics.backends {
+        let backend = escape_label(backend);
+        let mut cumulative = 0;
+        for (i, count) in backend_metrics.buckets.iter().enumerate() {
+            cumulative += count;
+            let le = DEFAULT_BUCKETS.get(i).map_or("+Inf".to_string(), |bound| bound.to_string());
+            result.push_str(&format!("l8r_request_duration_ms_bucket{{backend=\"{}\",le=\"{}\"}} {}\n", backend, le, cumulative));
+        }
+        result.push_str(&format!("l8r_request_duration_ms_sum{{backend=\"{}\"}} {}\n", backend, backend_metrics.duration_sum));
+        result.push_str(&format!("l8r_request_duration_ms_count{{backend=\"{}\"}} {}\n", backend, cumulative));
+    }
+    result
+}
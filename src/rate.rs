@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use chrono::DateTime;
+use colored::Colorize;
+
+use l8r::HaproxyLogEntry;
+use crate::utils::bold;
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// Requests per interval, keyed by the interval's start. The accept date carries the day,
+// so entries across midnight or out of order still land in their own interval
+#[derive(Debug, Default)]
+pub struct Rate {
+    pub intervals: BTreeMap<i64, u64>,
+    // Entries without a parseable accept date
+    pub undated: u64,
+}
+
+impl Rate {
+    pub fn add(&mut self, entry: &HaproxyLogEntry, interval: u64) {
+        match entry.accepted_at {
+            Some(t) => {
+                let t = t.and_utc().timestamp();
+                *self.intervals.entry(t - t.rem_euclid(interval as i64)).or_insert(0) += 1;
+            }
+            None => self.undated += 1,
+        }
+    }
+
+    pub fn scale(&mut self, scale: impl Fn(u64) -> u64) {
+        for count in self.intervals.values_mut() {
+            *count = scale(*count);
+        }
+        self.undated = scale(self.undated);
+    }
+
+    pub fn merge(mut self, other: Rate) -> Rate {
+        for (start, count) in other.intervals {
+            *self.intervals.entry(start).or_insert(0) += count;
+        }
+        self.undated += other.undated;
+        self
+    }
+
+    // Every interval from the first to the last entry, the ones without requests as 0
+    pub fn series(&self, interval: u64) -> Vec<(i64, u64)> {
+        let (first, last) = match (self.intervals.keys().next(), self.intervals.keys().next_back()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return vec![],
+        };
+        (first..=last).step_by(interval as usize)
+            .map(|start| (start, self.intervals.get(&start).copied().unwrap_or(0)))
+            .collect()
+    }
+}
+
+fn format_start(start: i64) -> String {
+    match DateTime::from_timestamp(start, 0) {
+        Some(t) => t.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "-".to_string(),
+    }
+}
+
+pub fn sparkline(series: &[(i64, u64)]) -> String {
+    let max = series.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    series.iter().map(|(_, count)| match max {
+        0 => SPARKS[0],
+        max => SPARKS[(*count as f64 / max as f64 * (SPARKS.len() - 1) as f64).round() as usize],
+    }).collect()
+}
+
+pub fn output_rate(rate: &Rate, interval: u64, spark: bool, color: bool) -> String {
+    let mut result = "".to_string();
+    let series = rate.series(interval);
+
+    if spark {
+        let line = sparkline(&series);
+        let peak = series.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        let (from, to) = match (series.first(), series.last()) {
+            (Some((from, _)), Some((to, _))) => (format_start(*from), format_start(*to)),
+            _ => ("-".to_string(), "-".to_string()),
+        };
+        result.push_str(&format!("{} {} {}\n", from, if color { line.cyan().to_string() } else { line }, to));
+        result.push_str(&format!("Peak {} requests per {}s\n", peak, interval));
+        return result;
+    }
+
+    let header = format!("{:<19} {:>10} {:>10}", "Interval", "Requests", "Per second");
+    result.push_str(&format!("{}\n", bold(&header, color)));
+    for (start, count) in series {
+        result.push_str(&format!("{:<19} {:>10} {:>10.2}\n", format_start(start), count, count as f64 / interval as f64));
+    }
+    result
+}
+
+pub fn output_rate_json(rate: &Rate, interval: u64) -> String {
+    let intervals: Vec<serde_json::Value> = rate.series(interval).into_iter().map(|(start, count)| {
+        serde_json::json!({ "interval": format_start(start), "requests": count, "per_second": count as f64 / interval as f64 })
+    }).collect();
+    serde_json::json!({ "interval_seconds": interval, "intervals": intervals, "undated": rate.undated }).to_string()
+}
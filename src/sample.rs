@@ -0,0 +1,35 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Keeps each line with a fixed probability, decided by hashing its index with the seed so
+// the same seed keeps the same lines however many threads parse them
+#[derive(Debug, Clone, Copy)]
+pub struct Sampler {
+    rate: f64,
+    seed: u64,
+}
+
+impl Sampler {
+    // A different sample every run without a seed
+    pub fn new(rate: f64, seed: Option<u64>) -> Sampler {
+        let seed = seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64));
+        Sampler { rate, seed }
+    }
+
+    pub fn keep(&self, index: u64) -> bool {
+        ((splitmix64(splitmix64(self.seed) ^ index) >> 11) as f64 / (1u64 << 53) as f64) < self.rate
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate <= 1.0 => Ok(rate),
+        _ => Err(format!("Invalid sample rate {}, expected a fraction above 0 and at most 1 like 0.01", s)),
+    }
+}
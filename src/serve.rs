@@ -0,0 +1,50 @@
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// Lines queued for a client that isn't reading before it starts missing some
+const CLIENT_BACKLOG: usize = 1024;
+
+// Pushes lines to every connected client. Each client gets its own writer thread, so a
+// slow one only loses lines of its own and parsing never waits on the network
+pub struct Server {
+    clients: Arc<Mutex<Vec<SyncSender<Arc<str>>>>>,
+}
+
+impl Server {
+    pub fn bind(addr: SocketAddr) -> std::io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        let clients: Arc<Mutex<Vec<SyncSender<Arc<str>>>>> = Arc::new(Mutex::new(vec![]));
+
+        let accepted = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (sender, receiver) = sync_channel(CLIENT_BACKLOG);
+                accepted.lock().unwrap().push(sender);
+                thread::spawn(move || write_client(stream, receiver.iter()));
+            }
+        });
+
+        Ok(Server { clients })
+    }
+
+    // Clients that went away are dropped here, the first time a line can't reach them
+    pub fn broadcast(&self, line: String) {
+        let line: Arc<str> = line.into();
+        self.clients.lock().unwrap().retain(|client| match client.try_send(line.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+// Returns once the client disconnects, which hangs up its channel
+fn write_client(mut stream: TcpStream, lines: impl Iterator<Item = Arc<str>>) {
+    for line in lines {
+        if writeln!(stream, "{}", line).is_err() {
+            return
+        }
+    }
+}
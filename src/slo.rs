@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use colored::Colorize;
+use serde::Serialize;
+
+use l8r::HaproxyLogEntry;
+use l8r::haproxy::StatusClass;
+use crate::utils::bold;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Objectives {
+    // Total time a request may take, with the share of requests that has to stay under it
+    pub latency: Option<(u64, f64)>,
+    // Share of requests that has to be served without a 5xx or an aborted session
+    pub availability: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct SloCounts {
+    pub total: u64,
+    pub slow: u64,
+    pub unavailable: u64,
+}
+
+impl SloCounts {
+    fn add(&mut self, entry: &HaproxyLogEntry, objectives: &Objectives) {
+        self.total += 1;
+        if objectives.latency.is_some_and(|(threshold, _)| entry.timers.total > threshold) {
+            self.slow += 1;
+        }
+        if entry.status_class() == StatusClass::ServerError || entry.termination_state.is_error() {
+            self.unavailable += 1;
+        }
+    }
+
+    fn merge(&mut self, other: SloCounts) {
+        self.total += other.total;
+        self.slow += other.slow;
+        self.unavailable += other.unavailable;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Slo {
+    pub overall: SloCounts,
+    pub backends: BTreeMap<String, SloCounts>,
+}
+
+impl Slo {
+    pub fn add(&mut self, entry: &HaproxyLogEntry, objectives: &Objectives) {
+        self.overall.add(entry, objectives);
+        self.backends.entry(entry.backend_name.to_string()).or_default().add(entry, objectives);
+    }
+
+    pub fn merge(mut self, other: Slo) -> Slo {
+        self.overall.merge(other.overall);
+        for (backend, counts) in other.backends {
+            self.backends.entry(backend).or_default().merge(counts);
+        }
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Compliance {
+    pub total: u64,
+    pub good: u64,
+    // Percentages, like the targets they are compared with
+    pub compliance: Option<f64>,
+    pub target: f64,
+    // How fast the error budget was spent, above 1 the scanned window used more than its share
+    pub burn_rate: Option<f64>,
+    pub budget_exceeded: bool,
+}
+
+impl Compliance {
+    fn new(total: u64, bad: u64, target: f64) -> Compliance {
+        let bad_ratio = match total {
+            0 => None,
+            total => Some(bad as f64 / total as f64),
+        };
+        let budget = 1.0 - target / 100.0;
+        let burn_rate = bad_ratio.map(|ratio| match budget {
+            b if b > 0.0 => ratio / b,
+            _ if ratio > 0.0 => f64::INFINITY,
+            _ => 0.0,
+        });
+
+        Compliance {
+            total,
+            good: total - bad,
+            compliance: bad_ratio.map(|ratio| (1.0 - ratio) * 100.0),
+            target,
+            burn_rate,
+            budget_exceeded: burn_rate.is_some_and(|rate| rate > 1.0),
+        }
+    }
+}
+
+// Every objective that was asked for, by name, for one set of counts
+fn evaluate(counts: &SloCounts, objectives: &Objectives) -> Vec<(&'static str, Compliance)> {
+    let mut result = vec![];
+    if let Some((_, target)) = objectives.latency {
+        result.push(("latency", Compliance::new(counts.total, counts.slow, target)));
+    }
+    if let Some(target) = objectives.availability {
+        result.push(("availability", Compliance::new(counts.total, counts.unavailable, target)));
+    }
+    result
+}
+
+fn label(objective: &str, objectives: &Objectives) -> String {
+    match (objective, objectives.latency) {
+        ("latency", Some((threshold, _))) => format!("latency <= {}ms", threshold),
+        (objective, _) => objective.to_string(),
+    }
+}
+
+fn format_row(objective: &str, backend: &str, compliance: &Compliance, color: bool) -> String {
+    let percent = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.3}%", v));
+    let budget = match (compliance.budget_exceeded, compliance.burn_rate) {
+        (_, None) => "-".normal(),
+        (true, _) => "exceeded".red(),
+        (false, _) => "ok".green(),
+    };
+    let budget = if color { budget.to_string() } else { budget.clear().to_string() };
+
+    format!("{:<20} {:<32} {:>10} {:>10} {:>11} {:>9} {:>10} {}",
+        objective, backend, compliance.total, compliance.good, percent(compliance.compliance),
+        format!("{}%", compliance.target), compliance.burn_rate.map_or("-".to_string(), |r| format!("{:.2}x", r)), budget)
+}
+
+pub fn output_slo(slo: &Slo, objectives: &Objectives, color: bool) -> String {
+    let mut result = "".to_string();
+    let header = format!("{:<20} {:<32} {:>10} {:>10} {:>11} {:>9} {:>10} {}",
+        "Objective", "Backend", "Requests", "Good", "Compliance", "Target", "Burn rate", "Budget");
+    result.push_str(&format!("{}\n", bold(&header, color)));
+
+    let backends: Vec<(&String, Vec<(&str, Compliance)>)> = slo.backends.iter()
+        .map(|(backend, counts)| (backend, evaluate(counts, objectives)))
+        .collect();
+    for (i, (objective, compliance)) in evaluate(&slo.overall, objectives).iter().enumerate() {
+        let objective = label(objective, objectives);
+        result.push_str(&format!("{}\n", format_row(&objective, "all", compliance, color)));
+        for (backend, evaluated) in &backends {
+            result.push_str(&format!("{}\n", format_row(&objective, backend, &evaluated[i].1, color)));
+        }
+    }
+    result
+}
+
+fn counts_json(counts: &SloCounts, objectives: &Objectives) -> serde_json::Value {
+    let mut value: serde_json::Value = evaluate(counts, objectives).into_iter()
+        .map(|(objective, compliance)| (objective.to_string(), serde_json::to_value(compliance).unwrap()))
+        .collect::<serde_json::Map<String, serde_json::Value>>()
+        .into();
+    if let Some((threshold, _)) = objectives.latency {
+        value["latency"]["threshold_ms"] = threshold.into();
+    }
+    value
+}
+
+pub fn output_slo_json(slo: &Slo, objectives: &Objectives) -> String {
+    let mut value = counts_json(&slo.overall, objectives);
+    value["backends"] = slo.backends.iter()
+        .map(|(backend, counts)| (backend.clone(), counts_json(counts, objectives)))
+        .collect::<serde_json::Map<String, serde_json::Value>>()
+        .into();
+    serde_json::to_string(&value).unwrap()
+}
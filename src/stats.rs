@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+
+use l8r::HaproxyLogEntry;
+use crate::utils::bold;
+
+#[derive(Debug, Default, Serialize)]
+pub struct BackendStats {
+    pub total: u64,
+    pub errors: u64,
+    // Sessions that didn't terminate normally, whatever their status code
+    pub terminations: u64,
+    pub status: BTreeMap<String, u64>,
+    pub bytes_read: u64,
+    // Entries logged with `-` instead of a byte count, left out of `bytes_read`
+    pub bytes_unknown: u64,
+    #[serde(skip)]
+    pub latencies: Vec<u64>,
+}
+
+impl BackendStats {
+    fn add(&mut self, entry: &HaproxyLogEntry) {
+        self.total += 1;
+        if entry.is_error() {
+            self.errors += 1;
+        }
+        if entry.termination_state.is_error() {
+            self.terminations += 1;
+        }
+        *self.status.entry(entry.status_class().to_string()).or_insert(0) += 1;
+        match entry.bytes_read {
+            Some(bytes) => self.bytes_read += bytes,
+            None => self.bytes_unknown += 1,
+        }
+        self.latencies.push(entry.timers.total);
+    }
+
+    fn merge(&mut self, other: BackendStats) {
+        self.total += other.total;
+        self.errors += other.errors;
+        self.terminations += other.terminations;
+        self.bytes_read += other.bytes_read;
+        self.bytes_unknown += other.bytes_unknown;
+        for (class, count) in other.status {
+            *self.status.entry(class).or_insert(0) += count;
+        }
+        self.latencies.extend(other.latencies);
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        ratio(self.errors, self.total)
+    }
+
+    pub fn status_rate(&self, class: &str) -> f64 {
+        ratio(self.status.get(class).copied().unwrap_or(0), self.total)
+    }
+
+    // Only valid after `Stats::finish` sorted the samples
+    pub fn percentile(&self, p: f64) -> Option<u64> {
+        percentile(&self.latencies, p)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    #[serde(flatten)]
+    pub overall: BackendStats,
+    pub backends: BTreeMap<String, BackendStats>,
+}
+
+impl Stats {
+    pub fn add(&mut self, entry: &HaproxyLogEntry) {
+        self.overall.add(entry);
+        self.backends.entry(entry.backend_name.to_string()).or_default().add(entry);
+    }
+
+    pub fn merge(mut self, other: Stats) -> Stats {
+        self.overall.merge(other.overall);
+        for (backend, stats) in other.backends {
+            self.backends.entry(backend).or_default().merge(stats);
+        }
+        self
+    }
+
+    pub fn finish(mut self) -> Stats {
+        self.overall.latencies.sort_unstable();
+        for stats in self.backends.values_mut() {
+            stats.latencies.sort_unstable();
+        }
+        self
+    }
+}
+
+// Nearest-rank percentile of already sorted samples
+pub fn percentile(sorted: &[u64], p: f64) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn ratio(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 / total as f64
+}
+
+#[derive(Debug, Serialize)]
+pub struct Latency {
+    pub p50: Option<u64>,
+    pub p95: Option<u64>,
+    pub p99: Option<u64>,
+    pub max: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackendSummary {
+    // Absent for the overall summary, which holds the backends instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    pub total: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub terminations: u64,
+    pub by_status: BTreeMap<String, u64>,
+    pub bytes_read: u64,
+    pub bytes_unknown: u64,
+    pub latency: Latency,
+}
+
+impl BackendSummary {
+    fn new(backend: Option<&str>, stats: &BackendStats) -> BackendSummary {
+        BackendSummary {
+            backend: backend.map(|b| b.to_string()),
+            total: stats.total,
+            errors: stats.errors,
+            error_rate: stats.error_rate(),
+            terminations: stats.terminations,
+            by_status: stats.status.clone(),
+            bytes_read: stats.bytes_read,
+            bytes_unknown: stats.bytes_unknown,
+            latency: Latency {
+                p50: stats.percentile(50.0),
+                p95: stats.percentile(95.0),
+                p99: stats.percentile(99.0),
+                max: stats.latencies.last().copied(),
+            },
+        }
+    }
+}
+
+// The serializable form of `Stats`, without the raw latency samples
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    #[serde(flatten)]
+    pub overall: BackendSummary,
+    pub by_backend: Vec<BackendSummary>,
+}
+
+impl Summary {
+    pub fn new(stats: &Stats) -> Summary {
+        Summary {
+            overall: BackendSummary::new(None, &stats.overall),
+            by_backend: stats.backends.iter()
+                .map(|(backend, stats)| BackendSummary::new(Some(backend), stats))
+                .collect(),
+        }
+    }
+}
+
+fn format_millis(value: Option<u64>) -> String {
+    value.map_or("-".to_string(), |v| format!("{}ms", v))
+}
+
+fn format_row(name: &str, summary: &BackendSummary) -> String {
+    format!("{:<32} {:>10} {:>10} {:>9.2}% {:>12} {:>8} {:>8} {:>8} {:>8}",
+        name, summary.total, summary.errors, summary.error_rate * 100.0, summary.terminations,
+        format_millis(summary.latency.p50), format_millis(summary.latency.p95),
+        format_millis(summary.latency.p99), format_millis(summary.latency.max))
+}
+
+pub fn output_summary(summary: &Summary, color: bool) -> String {
+    let mut result = "".to_string();
+
+    let header = format!("{:<32} {:>10} {:>10} {:>10} {:>12} {:>8} {:>8} {:>8} {:>8}",
+        "Backend", "Requests", "Errors", "Error rate", "Terminations", "p50", "p95", "p99", "Max");
+    result.push_str(&format!("{}\n", bold(&header, color)));
+    result.push_str(&format!("{}\n", bold(&format_row("all", &summary.overall), color)));
+    for backend in &summary.by_backend {
+        result.push_str(&format!("{}\n", format_row(backend.backend.as_deref().unwrap_or(""), backend)));
+    }
+
+    result.push_str(&format!("\n{}\n", bold(&format!("{:<32} {:>10} {:>10}", "Status", "Requests", "Share"), color)));
+    for (class, count) in &summary.overall.by_status {
+        result.push_str(&format!("{:<32} {:>10} {:>9.2}%\n", class, count, ratio(*count, summary.overall.total) * 100.0));
+    }
+
+    result.push_str(&format!("\n{} {} ({} entries without a byte count)\n",
+        bold("Bytes read:", color), summary.overall.bytes_read, summary.overall.bytes_unknown));
+    result
+}
+
+pub fn output_summary_json(summary: &Summary) -> String {
+    serde_json::to_string(summary).unwrap()
+}
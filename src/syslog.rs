@@ -0,0 +1,37 @@
+use chrono::DateTime;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// `<PRI>VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG`
+static RFC5424: Lazy<Regex> = regex_static::lazy_regex!(r#"^<\d{1,3}>\d{1,2}\s+(?P<timestamp>\S+)\s+(?P<host>\S+)\s+(?P<app>\S+)\s+(?P<procid>\S+)\s+\S+\s+(?:-|(?:\[[^\]]*\])+)(?:\s+(?P<message>.*))?$"#);
+// The RFC 3164 priority in front of an otherwise classic line
+static PRIORITY: Lazy<Regex> = regex_static::lazy_regex!(r#"^<\d{1,3}>"#);
+static CLASSIC_HEADER: Lazy<Regex> = regex_static::lazy_regex!(r#"^\p{L}{3,5}\.?\s+\d{1,2}\s+[0-9:]{8}\s"#);
+
+// Turns a line framed by a syslog relay back into the classic `Mon dd hh:mm:ss host
+// program[pid]: message` the log formats start with. An RFC 5424 header is rewritten into
+// one unless the message already carries its own, lines that aren't framed are left alone
+pub fn strip_syslog(line: String) -> String {
+    if let Some(captures) = RFC5424.captures(&line) {
+        let message = captures.name("message").map_or("", |m| m.as_str());
+        if CLASSIC_HEADER.is_match(message) {
+            return message.to_string()
+        }
+
+        let timestamp = match DateTime::parse_from_rfc3339(&captures["timestamp"]) {
+            Ok(t) => t.format("%b %e %H:%M:%S").to_string(),
+            Err(_) => return line,
+        };
+        // A relay may leave the pid out as -, which the program goes without then
+        let pid = match &captures["procid"] {
+            procid if procid.chars().all(|c| c.is_ascii_digit()) => format!("[{}]", procid),
+            _ => "".to_string(),
+        };
+        return format!("{} {} {}{}: {}", timestamp, &captures["host"], &captures["app"], pid, message)
+    }
+
+    match PRIORITY.find(&line) {
+        Some(priority) => line[priority.end()..].to_string(),
+        None => line,
+    }
+}
@@ -0,0 +1,64 @@
+use l8r::HaproxyLogEntry;
+use l8r::haproxy::{canonical_field_name, FIELD_NAMES};
+
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+// A line with `{field}` placeholders for any name --fields takes, `{{` and `}}` for literal braces
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let mut parts = vec![];
+        let mut literal = "".to_string();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = "".to_string();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("Unclosed {{ in --output-template {}", template)),
+                        }
+                    }
+                    let name = name.trim().to_string();
+                    if canonical_field_name(&name).is_none() {
+                        return Err(format!("Unknown field {{{}}} in --output-template, expected one of: {}", name, FIELD_NAMES.join(", ")));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(name));
+                }
+                '}' => return Err(format!("Unmatched }} in --output-template {}, write }}}} for a literal one", template)),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template { parts })
+    }
+
+    // Fields the entry doesn't have, like the response code of a TCP entry, are printed as -
+    pub fn render(&self, entry: &HaproxyLogEntry) -> String {
+        self.parts.iter().map(|part| match part {
+            Part::Literal(literal) => literal.clone(),
+            Part::Field(name) => entry.field(name).filter(|v| !v.is_empty()).unwrap_or_else(|| "-".to_string()),
+        }).collect()
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use colored::Colorize;
+
+use l8r::HaproxyLogEntry;
+use l8r::haproxy::HaproxyTerminationStateEntry;
+use crate::utils::bold;
+
+const POSITIONS: [(&str, &str); 4] = [
+    ("Termination reason", "termination_reason"),
+    ("Session state", "session_state"),
+    ("Persistence cookie", "persistence_cookie"),
+    ("Persistence operations", "persistence_operations"),
+];
+
+// Descriptions longer than this are cut at their first comma too
+const SHORT_DESCRIPTION_WIDTH: usize = 80;
+
+fn describe(position: usize, flag: char) -> String {
+    match position {
+        0 => HaproxyTerminationStateEntry::reason(flag),
+        1 => HaproxyTerminationStateEntry::state(flag),
+        2 => HaproxyTerminationStateEntry::cookie(flag),
+        _ => HaproxyTerminationStateEntry::operations(flag),
+    }.description
+}
+
+// The first sentence, without what's in parentheses
+fn short_description(description: &str) -> &str {
+    let end = [". ", " (", "."].iter().filter_map(|sep| description.find(sep)).min().unwrap_or(description.len());
+    let description = &description[..end];
+    match description.len() > SHORT_DESCRIPTION_WIDTH {
+        true => &description[..description.find(", ").unwrap_or(description.len())],
+        false => description,
+    }
+}
+
+// The reason and state pair HAProxy troubleshooting goes by, like CD, and every flag on its own.
+// TCP entries only have the first two
+#[derive(Debug, Default)]
+pub struct TermSummary {
+    pub states: HashMap<String, u64>,
+    pub flags: [HashMap<char, u64>; 4],
+}
+
+impl TermSummary {
+    pub fn add(&mut self, entry: &HaproxyLogEntry) {
+        let raw = &entry.termination_state.raw;
+        *self.states.entry(raw.chars().take(2).collect()).or_insert(0) += 1;
+        for (flags, flag) in self.flags.iter_mut().zip(raw.chars()) {
+            *flags.entry(flag).or_insert(0) += 1;
+        }
+    }
+
+    pub fn merge(mut self, other: TermSummary) -> TermSummary {
+        for (state, count) in other.states {
+            *self.states.entry(state).or_insert(0) += count;
+        }
+        for (flags, other) in self.flags.iter_mut().zip(other.flags) {
+            for (flag, count) in other {
+                *flags.entry(flag).or_insert(0) += count;
+            }
+        }
+        self
+    }
+
+    pub fn scale(&mut self, scale: impl Fn(u64) -> u64) {
+        for count in self.states.values_mut().chain(self.flags.iter_mut().flat_map(|flags| flags.values_mut())) {
+            *count = scale(*count);
+        }
+    }
+}
+
+// Most common first
+fn sorted<K: Ord + Clone>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut sorted: Vec<(K, u64)> = counts.iter().map(|(k, count)| (k.clone(), *count)).collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    sorted
+}
+
+pub fn output_term_summary(summary: &TermSummary, color: bool) -> String {
+    // Anything but - is out of the ordinary for the reason and the state
+    let flag = |s: String, abnormal: bool| match color && abnormal {
+        true => s.red().to_string(),
+        false => s,
+    };
+
+    let mut result = format!("{}\n", bold("Termination states", color));
+    for (state, count) in sorted(&summary.states) {
+        let descriptions: Vec<String> = state.chars().enumerate()
+            .map(|(position, c)| short_description(&describe(position, c)).to_string())
+            .collect();
+        result.push_str(&format!("  {}: {} ({})\n", flag(state.clone(), state.chars().any(|c| c != '-')), count, descriptions.join("; ")));
+    }
+
+    for (position, (label, _)) in POSITIONS.iter().enumerate() {
+        if summary.flags[position].is_empty() {
+            continue
+        }
+        result.push_str(&format!("\n{}\n", bold(label, color)));
+        for (c, count) in sorted(&summary.flags[position]) {
+            let description = describe(position, c);
+            result.push_str(&format!("  {}: {} ({})\n", flag(c.to_string(), position < 2 && c != '-'), count, short_description(&description)));
+        }
+    }
+    result
+}
+
+pub fn output_term_summary_json(summary: &TermSummary) -> String {
+    let states: Vec<serde_json::Value> = sorted(&summary.states).into_iter().map(|(state, count)| {
+        let mut value = serde_json::json!({ "state": state, "count": count });
+        for (position, c) in state.chars().enumerate() {
+            value[POSITIONS[position].1] = describe(position, c).into();
+        }
+        value
+    }).collect();
+
+    let mut result = serde_json::json!({ "states": states });
+    for (position, (_, key)) in POSITIONS.iter().enumerate() {
+        result[*key] = sorted(&summary.flags[position]).into_iter().map(|(c, count)| {
+            serde_json::json!({ "flag": c.to_string(), "count": count, "description": describe(position, c) })
+        }).collect::<Vec<serde_json::Value>>().into();
+    }
+    result.to_string()
+}
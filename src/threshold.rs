@@ -0,0 +1,131 @@
+use colored::Colorize;
+use serde::Serialize;
+
+use l8r::haproxy::StatusClass;
+use l8r::HaproxyLogEntry;
+
+// Exit code for a run whose thresholds were exceeded. Other failures exit with 1, and
+// usage errors with 2
+pub const EXIT_THRESHOLD_EXCEEDED: i32 = 3;
+
+// Returned by `run` so `main` can exit with `EXIT_THRESHOLD_EXCEEDED` once the report is out
+#[derive(Debug)]
+pub struct ThresholdsExceeded;
+
+impl std::fmt::Display for ThresholdsExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Thresholds exceeded")
+    }
+}
+
+impl std::error::Error for ThresholdsExceeded {}
+
+// A limit given as a percentage of the matching entries like 1%, or an absolute count like 100
+#[derive(Debug, Clone, Copy)]
+pub enum Threshold {
+    Percent(f64),
+    Count(u64),
+}
+
+impl Threshold {
+    fn exceeded(&self, count: u64, total: u64) -> bool {
+        match *self {
+            Threshold::Percent(pct) => total > 0 && count as f64 / total as f64 * 100.0 > pct,
+            Threshold::Count(max) => count > max,
+        }
+    }
+}
+
+impl std::fmt::Display for Threshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Threshold::Percent(pct) => write!(f, "{}%", pct),
+            Threshold::Count(max) => write!(f, "{}", max),
+        }
+    }
+}
+
+impl std::str::FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid threshold {}, expected a percentage like 1% or a count like 100", s);
+        match s.strip_suffix('%') {
+            Some(pct) => pct.parse::<f64>().ok().filter(|pct| (0.0..=100.0).contains(pct)).map(Threshold::Percent).ok_or_else(invalid),
+            None => s.parse().map(Threshold::Count).map_err(|_| invalid()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Counts {
+    pub total: u64,
+    pub errors: u64,
+    pub server_errors: u64,
+}
+
+impl Counts {
+    pub fn add(&mut self, entry: &HaproxyLogEntry) {
+        self.total += 1;
+        if entry.is_error() {
+            self.errors += 1;
+        }
+        if entry.status_class() == StatusClass::ServerError {
+            self.server_errors += 1;
+        }
+    }
+
+    pub fn merge(mut self, other: Counts) -> Counts {
+        self.total += other.total;
+        self.errors += other.errors;
+        self.server_errors += other.server_errors;
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub count: u64,
+    pub limit: String,
+    pub exceeded: bool,
+}
+
+pub fn check(counts: &Counts, errors: Option<Threshold>, server_errors: Option<Threshold>) -> Vec<Check> {
+    [("errors", counts.errors, errors), ("5xx", counts.server_errors, server_errors)].into_iter()
+        .filter_map(|(name, count, threshold)| threshold.map(|threshold| Check {
+            name,
+            count,
+            limit: threshold.to_string(),
+            exceeded: threshold.exceeded(count, counts.total),
+        }))
+        .collect()
+}
+
+fn pct(count: u64, total: u64) -> f64 {
+    match total {
+        0 => 0.0,
+        total => count as f64 / total as f64 * 100.0,
+    }
+}
+
+pub fn output_checks(counts: &Counts, checks: &[Check], color: bool) -> String {
+    let mut result = format!("Checked {} entries\n", counts.total);
+    for check in checks {
+        let verdict = match check.exceeded {
+            true => "FAIL".red(),
+            false => "PASS".green(),
+        };
+        let verdict = if color { verdict.bold().to_string() } else { verdict.clear().to_string() };
+        result.push_str(&format!("{}: {} {} ({:.2}%), limit {}\n", verdict, check.count, check.name, pct(check.count, counts.total), check.limit));
+    }
+    result
+}
+
+pub fn output_checks_json(counts: &Counts, checks: &[Check]) -> String {
+    serde_json::json!({
+        "total": counts.total,
+        "checks": checks,
+        "passed": !checks.iter().any(|check| check.exceeded),
+    }).to_string()
+}
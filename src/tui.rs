@@ -0,0 +1,228 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use regex::Regex;
+
+use l8r::{HaproxyLogEntry, Theme};
+use crate::filter::Filter;
+use crate::utils::output_table;
+
+// One whitespace separated term of the interactive filter, all terms must match
+enum Term {
+    Backend(String),
+    Status(String),
+    Pattern(Regex),
+}
+
+impl Term {
+    fn parse(s: &str) -> Result<Term, regex::Error> {
+        if let Some(backend) = s.strip_prefix("backend=") {
+            return Ok(Term::Backend(backend.to_string()));
+        }
+        if let Some(status) = s.strip_prefix("status=") {
+            return Ok(Term::Status(status.to_string()));
+        }
+        Ok(Term::Pattern(Regex::new(s)?))
+    }
+
+    fn matches(&self, line: &str, entry: &HaproxyLogEntry) -> bool {
+        match self {
+            Term::Backend(backend) => entry.backend_name == backend,
+            // Either an exact code or a class like 5xx
+            Term::Status(status) => entry.response_code_raw == Some(status.as_str()) || entry.status_class().to_string() == *status,
+            Term::Pattern(regex) => regex.is_match(line),
+        }
+    }
+}
+
+struct App<'a> {
+    // Each entry alongside the line it was parsed from
+    entries: &'a [(&'a str, HaproxyLogEntry<'a>)],
+    visible: Vec<usize>,
+    state: ListState,
+    filter: String,
+    filter_error: Option<String>,
+    editing: bool,
+    theme: Theme,
+}
+
+impl<'a> App<'a> {
+    fn apply_filter(&mut self) {
+        let terms: Result<Vec<Term>, regex::Error> = self.filter.split_whitespace().map(Term::parse).collect();
+        match terms {
+            Ok(terms) => {
+                self.filter_error = None;
+                self.visible = (0..self.entries.len())
+                    .filter(|&i| terms.iter().all(|t| t.matches(self.entries[i].0, &self.entries[i].1)))
+                    .collect();
+                self.state.select(if self.visible.is_empty() { None } else { Some(0) });
+            }
+            // Keep the previous results while the user is halfway through typing a regex
+            Err(e) => self.filter_error = Some(e.to_string()),
+        }
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.state.selected().unwrap_or(0) as isize;
+        let last = self.visible.len() as isize - 1;
+        self.state.select(Some((current + offset).clamp(0, last) as usize));
+    }
+
+    fn selected(&self) -> Option<&HaproxyLogEntry<'a>> {
+        self.state.selected().and_then(|i| self.visible.get(i)).map(|&i| &self.entries[i].1)
+    }
+}
+
+fn tui_color(color: colored::Color) -> Color {
+    match color {
+        colored::Color::Black => Color::Black,
+        colored::Color::Red => Color::Red,
+        colored::Color::Green => Color::Green,
+        colored::Color::Yellow => Color::Yellow,
+        colored::Color::Blue => Color::Blue,
+        colored::Color::Magenta => Color::Magenta,
+        colored::Color::Cyan => Color::Cyan,
+        colored::Color::White => Color::White,
+        colored::Color::BrightBlack => Color::DarkGray,
+        colored::Color::BrightRed => Color::LightRed,
+        colored::Color::BrightGreen => Color::LightGreen,
+        colored::Color::BrightYellow => Color::LightYellow,
+        colored::Color::BrightBlue => Color::LightBlue,
+        colored::Color::BrightMagenta => Color::LightMagenta,
+        colored::Color::BrightCyan => Color::LightCyan,
+        colored::Color::BrightWhite => Color::Gray,
+        colored::Color::TrueColor { r, g, b } => Color::Rgb(r, g, b),
+    }
+}
+
+// Mirrors the colors of `HaproxyLogEntry::colorize_themed` with ratatui styles
+fn entry_line<'a>(entry: &HaproxyLogEntry<'a>, theme: &Theme) -> Line<'a> {
+    let style = |color: colored::Color| Style::default().fg(tui_color(color));
+    let request = entry.request_text();
+    let (method, rest) = match entry.request.as_ref().and_then(|r| r.method).and_then(|method| Some((method, request.strip_prefix(method)?))) {
+        Some((method, rest)) => (Some(method), rest),
+        None => (None, request),
+    };
+    let mut spans = vec![
+        Span::styled(format!("{} {} {} ", entry.month, entry.day, entry.time), style(theme.text)),
+        Span::styled(format!("{} ", entry.frontend_name), style(theme.frontend)),
+        Span::styled(entry.backend_name, style(theme.backend)),
+        Span::styled("/", style(theme.text)),
+        Span::styled(format!("{} ", entry.server_name), style(theme.server)),
+        Span::styled(format!("{} ", entry.timers), style(theme.text)),
+        Span::styled(format!("{} ", entry.response_code_raw.unwrap_or("-")), style(theme.status(entry.status_class()))),
+        Span::styled(format!("{} ", entry.termination_state), style(theme.termination(&entry.termination_state))),
+    ];
+    spans.extend(method.map(|method| Span::styled(method.to_string(), style(theme.method(method)))));
+    spans.push(Span::styled(rest.to_string(), style(theme.text)));
+    Line::from(spans)
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let [filter_area, list_area, detail_area, help_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Percentage(55),
+        Constraint::Min(5),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+
+    let filter_title = match &app.filter_error {
+        Some(e) => format!("Filter (invalid: {})", e.lines().last().unwrap_or_default()),
+        None => format!("Filter ({}/{} entries)", app.visible.len(), app.entries.len()),
+    };
+    let filter_style = if app.editing { Style::default().fg(Color::Yellow) } else { Style::default() };
+    frame.render_widget(
+        Paragraph::new(app.filter.as_str()).block(Block::default().borders(Borders::ALL).title(filter_title).border_style(filter_style)),
+        filter_area,
+    );
+
+    let items: Vec<ListItem> = app.visible.iter().map(|&i| ListItem::new(entry_line(&app.entries[i].1, &app.theme))).collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Entries"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, list_area, &mut app.state);
+
+    let detail = app.selected().and_then(|entry| output_table(entry, &app.theme).ok()).unwrap_or_default();
+    frame.render_widget(
+        Paragraph::new(detail).wrap(Wrap { trim: false }).block(Block::default().borders(Borders::ALL).title("Details")),
+        detail_area,
+    );
+
+    let help = if app.editing {
+        "Enter/Esc: stop editing   terms: <regex> backend=<name> status=<code|5xx>"
+    } else {
+        "/: filter   j/k ↑/↓: move   PgUp/PgDn   g/G: first/last   q: quit"
+    };
+    frame.render_widget(Paragraph::new(help).style(Style::default().fg(Color::DarkGray)), help_area);
+}
+
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> std::io::Result<()> {
+    loop {
+        terminal.draw(|frame| draw(frame, app))?;
+
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        if app.editing {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => app.editing = false,
+                KeyCode::Backspace => {
+                    app.filter.pop();
+                    app.apply_filter();
+                }
+                KeyCode::Char(c) => {
+                    app.filter.push(c);
+                    app.apply_filter();
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('/') => app.editing = true,
+            KeyCode::Down | KeyCode::Char('j') => app.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => app.move_selection(-1),
+            KeyCode::PageDown => app.move_selection(20),
+            KeyCode::PageUp => app.move_selection(-20),
+            KeyCode::Home | KeyCode::Char('g') => app.move_selection(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => app.move_selection(isize::MAX / 2),
+            _ => {}
+        }
+    }
+}
+
+// Every line comes with what `Filter::map_line` made of it
+pub fn run_tui(lines: &[(String, String)], filter: &Filter, theme: Theme) -> Result<(), Box<dyn std::error::Error>> {
+    let entries: Vec<(&str, HaproxyLogEntry)> = lines.iter()
+        .filter_map(|(line, mapped)| filter.parse(line, mapped).ok().map(|entry| (line.as_str(), entry)))
+        .collect();
+
+    let mut app = App {
+        entries: &entries,
+        visible: (0..entries.len()).collect(),
+        state: ListState::default(),
+        filter: "".to_string(),
+        filter_error: None,
+        editing: false,
+        theme,
+    };
+    app.state.select(if entries.is_empty() { None } else { Some(0) });
+
+    // The detail pane is plain text, so keep `output_table` from emitting escape codes
+    colored::control::set_override(false);
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut app);
+    ratatui::restore();
+    colored::control::unset_override();
+    Ok(result?)
+}
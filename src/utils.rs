@@ -1,8 +1,11 @@
 use atty::Stream;
+use chrono::NaiveDateTime;
 use colored::Colorize;
 use anyhow::Result;
 
-use crate::haproxy::HaproxyLogEntry;
+use l8r::{HaproxyLogEntry, Theme, Timer};
+use l8r::haproxy::canonical_field_name;
+use crate::geoip::Geo;
 
 pub fn is_stdin_redirected() -> Result<bool> {
     if atty::is(Stream::Stdin) {
@@ -12,70 +15,100 @@ pub fn is_stdin_redirected() -> Result<bool> {
     Ok(true)
 }
 
-pub fn output_table(entry: &HaproxyLogEntry) -> Result<String> {
+pub fn output_table(entry: &HaproxyLogEntry, theme: &Theme) -> Result<String> {
     let mut result = "".to_string();
     
-    result.push_str(&format!("{}: {}\n", "Month".bold(), entry.month.white()));
-    result.push_str(&format!("{}: {}\n", "Day".bold(), entry.day.white()));
-    result.push_str(&format!("{}: {}\n", "Time".bold(), entry.time.white()));
-    result.push_str(&format!("{}: {}\n", "Host".bold(), entry.host.white()));
-    result.push_str(&format!("{}: {}\n", "Process ID".bold(), entry.process_id.white()));
-    result.push_str(&format!("{}: {}\n", "Source IP Port".bold(), entry.source_ip_port.white()));
-    result.push_str(&format!("{}: {}\n", "Time Stamp Accepted".bold(), entry.time_stamp_accepted.white()));
-    result.push_str(&format!("{}: {}\n", "Frontend Name".bold(), entry.frontend_name.purple()));
-    result.push_str(&format!("{}: {}\n", "Backend Name".bold(), entry.backend_name.yellow()));
-    result.push_str(&format!("{}: {}\n", "Server Name".bold(), entry.server_name.blue()));
-    result.push_str(&format!("{}: {}\n", "Timers".bold(), entry.timers.to_string().white()));
-
-    result.push_str(&format!("∟ {}: {}\n", "Client Request".bold(), entry.timers.client_request.to_string().white()));
-    result.push_str(&format!("∟ {}: {}\n", "Queue Wait".bold(), entry.timers.queue_wait.to_string().white()));
-    result.push_str(&format!("∟ {}: {}\n", "Establish".bold(), entry.timers.establish.to_string().white()));
-    result.push_str(&format!("∟ {}: {}\n", "Server Response".bold(), entry.timers.server_response.to_string().white()));
-    result.push_str(&format!("∟ {}: {}\n", "Total".bold(), entry.timers.total.to_string().white()));
-
-    result.push_str(&format!("{}: {}\n", "Response Code".bold(), match entry.response_code.parse::<u16>() {
-        Ok(code) => {
-            if code >= 200 && code < 300 {
-                entry.response_code.green()
-            } else if code >= 300 && code < 400 {
-                entry.response_code.yellow()
-            } else if code >= 400 {
-                entry.response_code.red()
-            } else {
-                entry.response_code.white()
-            }
-        }
-        Err(_) => entry.response_code.white()
-    }));
-    result.push_str(&format!("{}: {}\n", "Bytes Read".bold(), entry.bytes_read.white()));
-    result.push_str(&format!("{}: {}\n", "Termination State".bold(), match entry.termination_state.is_error() {
-        false => entry.termination_state.to_string().green(),
-        true => entry.termination_state.to_string().red()
-    }));
+    result.push_str(&format!("{}: {}\n", "Month".bold(), entry.month.color(theme.text)));
+    result.push_str(&format!("{}: {}\n", "Day".bold(), entry.day.color(theme.text)));
+    result.push_str(&format!("{}: {}\n", "Time".bold(), entry.time.color(theme.text)));
+    result.push_str(&format!("{}: {}\n", "Host".bold(), entry.host.color(theme.text)));
+    result.push_str(&format!("{}: {}\n", "Process ID".bold(), entry.process_id.color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "Program".bold(), entry.program.color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "PID".bold(), entry.pid.map_or("-".to_string(), |pid| pid.to_string()).color(theme.text)));
+    result.push_str(&format!("{}: {}\n", "Source IP Port".bold(), entry.source.raw.color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "IP".bold(), entry.source.ip.color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "Port".bold(), entry.source.port.to_string().color(theme.text)));
+    result.push_str(&format!("{}: {}\n", "Time Stamp Accepted".bold(), entry.time_stamp_accepted.color(theme.text)));
+    result.push_str(&format!("{}: {}\n", "Frontend Name".bold(), entry.frontend_name.color(theme.frontend)));
+    result.push_str(&format!("{}: {}\n", "Backend Name".bold(), entry.backend_name.color(theme.backend)));
+    result.push_str(&format!("{}: {}\n", "Server Name".bold(), entry.server_name.color(theme.server)));
+    result.push_str(&format!("{}: {}\n", "Timers".bold(), entry.timers.to_string().color(theme.text)));
+
+    // Phases logged as -1 never completed
+    let timer = |value: Option<u64>| value.map_or("not measured".to_string(), |t| t.to_string()).color(theme.text);
+    if entry.timers.logged(Timer::ClientRequest) {
+        result.push_str(&format!("∟ {}: {}\n", "Client Request".bold(), timer(entry.timers.client_request)));
+    }
+    result.push_str(&format!("∟ {}: {}\n", "Queue Wait".bold(), timer(entry.timers.queue_wait)));
+    result.push_str(&format!("∟ {}: {}\n", "Establish".bold(), timer(entry.timers.establish)));
+    if entry.timers.logged(Timer::ServerResponse) {
+        result.push_str(&format!("∟ {}: {}\n", "Server Response".bold(), timer(entry.timers.server_response)));
+    }
+    result.push_str(&format!("∟ {}: {}\n", "Total".bold(), entry.timers.total.to_string().color(theme.text)));
+
+    if let Some(code) = entry.response_code_raw {
+        result.push_str(&format!("{}: {}\n", "Response Code".bold(), code.color(theme.status(entry.status_class()))));
+    }
+    result.push_str(&format!("{}: {}\n", "Bytes Read".bold(), entry.bytes_read_raw.color(theme.text)));
+    result.push_str(&format!("{}: {}\n", "Termination State".bold(), entry.termination_state.to_string().color(theme.termination(&entry.termination_state))));
 
-    result.push_str(&format!("∟ {}: {}\n", "Termination Reason".bold(), entry.termination_state.termination_reason.description.white()));
-    result.push_str(&format!("∟ {}: {}\n", "Session State".bold(), entry.termination_state.session_state.description.white()));
-    result.push_str(&format!("∟ {}: {}\n", "Persistence Cookie".bold(), entry.termination_state.persistence_cookie.description.white()));
-    result.push_str(&format!("∟ {}: {}\n", "Persistence Operations".bold(), entry.termination_state.persistence_operations.description.white()));
+    result.push_str(&format!("∟ {}: {}\n", "Termination Reason".bold(), entry.termination_state.termination_reason.description.color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "Session State".bold(), entry.termination_state.session_state.description.color(theme.text)));
+    if let Some(cookie) = &entry.termination_state.persistence_cookie {
+        result.push_str(&format!("∟ {}: {}\n", "Persistence Cookie".bold(), cookie.description.color(theme.text)));
+    }
+    if let Some(operations) = &entry.termination_state.persistence_operations {
+        result.push_str(&format!("∟ {}: {}\n", "Persistence Operations".bold(), operations.description.color(theme.text)));
+    }
 
-    result.push_str(&format!("{}: {}\n", "Connection Counts".bold(), entry.conn_counts.to_string().white()));
+    result.push_str(&format!("{}: {}\n", "Connection Counts".bold(), entry.conn_counts.to_string().color(theme.text)));
 
-    result.push_str(&format!("∟ {}: {}\n", "Current".bold(), entry.conn_counts.current.to_string().white()));
-    result.push_str(&format!("∟ {}: {}\n", "Limit".bold(), entry.conn_counts.limit.to_string().white()));
-    result.push_str(&format!("∟ {}: {}\n", "Max".bold(), entry.conn_counts.max.to_string().white()));
-    result.push_str(&format!("∟ {}: {}\n", "Total".bold(), entry.conn_counts.total.to_string().white()));
-    result.push_str(&format!("∟ {}: {}\n", "Rejected".bold(), entry.conn_counts.rejected.to_string().white()));
+    result.push_str(&format!("∟ {}: {}\n", "Current".bold(), entry.conn_counts.current.to_string().color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "Limit".bold(), entry.conn_counts.limit.to_string().color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "Max".bold(), entry.conn_counts.max.to_string().color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "Total".bold(), entry.conn_counts.total.to_string().color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "Rejected".bold(), entry.conn_counts.rejected.to_string().color(theme.text)));
 
-    result.push_str(&format!("{}: {}\n", "Queue".bold(), entry.queue.to_string().white()));
+    result.push_str(&format!("{}: {}\n", "Queue".bold(), entry.queue.to_string().color(theme.text)));
 
-    result.push_str(&format!("∟ {}: {}\n", "Server".bold(), entry.queue.server.to_string().white()));
-    result.push_str(&format!("∟ {}: {}\n", "Backend".bold(), entry.queue.backend.to_string().white()));
+    result.push_str(&format!("∟ {}: {}\n", "Server".bold(), entry.queue.server.to_string().color(theme.text)));
+    result.push_str(&format!("∟ {}: {}\n", "Backend".bold(), entry.queue.backend.to_string().color(theme.text)));
 
-    result.push_str(&format!("{}: {}\n", "Request".bold(), entry.request.white()));
+    if let Some(request) = &entry.request {
+        result.push_str(&format!("{}: {}\n", "Request".bold(), request.raw.as_ref().color(theme.text)));
+        if let (Some(method), Some(path)) = (request.method, &request.path) {
+            result.push_str(&format!("∟ {}: {}\n", "Method".bold(), method.color(theme.text)));
+            result.push_str(&format!("∟ {}: {}\n", "Path".bold(), path.color(theme.text)));
+            if let Some(query) = request.query {
+                result.push_str(&format!("∟ {}: {}\n", "Query".bold(), query.color(theme.text)));
+            }
+            if let Some(protocol) = request.protocol {
+                result.push_str(&format!("∟ {}: {}\n", "Protocol".bold(), protocol.color(theme.text)));
+            }
+        }
+    }
 
     Ok(result)
 }
 
+// `output_table` cut down to the chosen fields, in their order and labelled as they were given
+pub fn output_table_fields(entry: &HaproxyLogEntry, theme: &Theme, fields: &[String]) -> String {
+    let mut result = "".to_string();
+    for name in fields {
+        let color = match canonical_field_name(name) {
+            Some("frontend_name") => theme.frontend,
+            Some("backend_name") => theme.backend,
+            Some("server_name") => theme.server,
+            Some("response_code") | Some("status_class") => theme.status(entry.status_class()),
+            Some("termination_state") => theme.termination(&entry.termination_state),
+            _ => theme.text,
+        };
+        let value = entry.field(name).filter(|v| !v.is_empty()).unwrap_or_else(|| "-".to_string());
+        result.push_str(&format!("{}: {}\n", name.bold(), value.color(color)));
+    }
+    result
+}
+
 #[cfg(unix)]
 pub fn reset_sigpipe() {
     unsafe {
@@ -87,3 +120,140 @@ pub fn reset_sigpipe() {
 pub fn reset_sigpipe() {
     // no-op
 }
+
+// Everything written to stdout goes to the pager until this is dropped
+pub struct Pager {
+    child: std::process::Child,
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        use std::io::Write;
+        let _ = std::io::stdout().flush();
+        // The pager only sees the end of its input once nothing holds the pipe open anymore
+        #[cfg(unix)]
+        unsafe {
+            libc::close(libc::STDOUT_FILENO);
+        }
+        let _ = self.child.wait();
+    }
+}
+
+// $PAGER, or less, with stdout moved onto its stdin. Like git, LESS defaults to FRX so colors
+// come through and output that fits on one screen is printed without waiting for a keypress
+#[cfg(unix)]
+pub fn start_pager() -> Option<Pager> {
+    use std::os::fd::AsRawFd;
+    use std::process::{Command, Stdio};
+
+    let pager = std::env::var("PAGER").ok().filter(|p| !p.is_empty()).unwrap_or_else(|| "less".to_string());
+    if pager == "cat" {
+        return None;
+    }
+    // Run directly instead of through a shell, so a missing pager fails to spawn and output
+    // goes to the terminal as if there was none
+    let mut words = pager.split_whitespace();
+    let mut command = Command::new(words.next()?);
+    command.args(words).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+
+    let mut child = command.spawn().ok()?;
+    let stdin = child.stdin.take()?;
+    // A pager that quits early closes the pipe, and the default SIGPIPE from `reset_sigpipe` ends l8r quietly
+    if unsafe { libc::dup2(stdin.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        let _ = child.kill();
+        let _ = child.wait();
+        return None;
+    }
+    Some(Pager { child })
+}
+
+#[cfg(not(unix))]
+pub fn start_pager() -> Option<Pager> {
+    None
+}
+
+// Durations like `30s`, `5m`, `1h` or `1d`, a bare number is taken as seconds
+pub fn parse_duration(s: &str) -> Result<u64, String> {
+    let (value, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let value: u64 = value.parse().map_err(|_| format!("Invalid duration: {}", s))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration unit in {}, expected s, m, h or d", s)),
+    };
+    if value == 0 {
+        return Err("Duration must be greater than zero".to_string());
+    }
+    value.checked_mul(multiplier).ok_or_else(|| format!("Duration too large: {}", s))
+}
+
+// Takes \t, \n and \\ as the characters they stand for, shells pass them along as typed
+pub fn parse_separator(s: &str) -> Result<String, String> {
+    let mut separator = "".to_string();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => separator.push('\t'),
+                Some('n') => separator.push('\n'),
+                Some('\\') => separator.push('\\'),
+                Some(other) => return Err(format!("Unknown escape \\{} in separator, expected \\t, \\n or \\\\", other)),
+                None => return Err("Separator ends in a lone \\".to_string()),
+            },
+            c => separator.push(c),
+        }
+    }
+    match separator.is_empty() {
+        true => Err("Separator can't be empty".to_string()),
+        false => Ok(separator),
+    }
+}
+
+// HAProxy's own accept date format, optionally with milliseconds, or an ISO 8601 date and time
+pub fn parse_timestamp(s: &str) -> Result<NaiveDateTime, String> {
+    ["%d/%b/%Y:%H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .ok_or_else(|| format!("Invalid timestamp {}, expected one like 08/May/2024:00:08:00 or 2024-05-08T00:08:00", s))
+}
+
+// Appended to wide output for --geoip, "-" for what the databases don't know
+pub fn output_geo(geo: &Geo, theme: &Theme) -> String {
+    format!("{}: {}\n{}: {}\n",
+        "Geo Country".bold(), geo.country.as_deref().unwrap_or("-").color(theme.text),
+        "Geo ASN".bold(), geo.asn.map_or("-".to_string(), |asn| asn.to_string()).color(theme.text))
+}
+
+// Headers and totals of the summaries, plain when they aren't colored
+pub fn bold(s: &str, color: bool) -> String {
+    match color {
+        true => s.bold().to_string(),
+        false => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("5m"), Ok(300));
+        assert_eq!(parse_duration("1d"), Ok(86400));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("5w").is_err());
+    }
+
+    #[test]
+    fn duration_overflow() {
+        assert_eq!(parse_duration("99999999999999999d"), Err("Duration too large: 99999999999999999d".to_string()));
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::filter::Filter;
+
+// Failing lines kept for the report, the rest are only counted
+const SHOWN_FAILURES: usize = 10;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Validation {
+    pub total: u64,
+    pub parsed: u64,
+    // Line numbers are 1-based, like an editor shows them
+    pub failures: Vec<(usize, String)>,
+    // Every failure, named by `ParseError::kind`
+    pub by_kind: BTreeMap<&'static str, u64>,
+}
+
+impl Validation {
+    // Only the log format of the filter applies, blank lines aren't entries, so they neither pass nor fail
+    pub fn check(lines: impl Iterator<Item = String>, filter: &Filter) -> Validation {
+        let mut validation = Validation::default();
+        for (i, line) in lines.enumerate() {
+            if line.trim().is_empty() {
+                continue
+            }
+
+            validation.total += 1;
+            match filter.parse(&line, &filter.map_line(&line)) {
+                Ok(_) => validation.parsed += 1,
+                Err(e) => {
+                    *validation.by_kind.entry(e.kind()).or_insert(0) += 1;
+                    if validation.failures.len() < SHOWN_FAILURES {
+                        validation.failures.push((i + 1, line));
+                    }
+                }
+            }
+        }
+        validation
+    }
+
+    pub fn unparsed(&self) -> u64 {
+        self.total - self.parsed
+    }
+
+    pub fn unparsed_pct(&self) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => self.unparsed() as f64 / total as f64 * 100.0,
+        }
+    }
+
+    pub fn passed(&self, max_unparsed_pct: f64) -> bool {
+        self.unparsed_pct() <= max_unparsed_pct
+    }
+}
+
+pub fn output_validation(validation: &Validation, max_unparsed_pct: f64, color: bool) -> String {
+    let mut result = format!("Parsed {} of {} lines ({:.2}%)\n",
+        validation.parsed, validation.total, 100.0 - validation.unparsed_pct());
+
+    if !validation.failures.is_empty() {
+        match validation.unparsed() as usize > validation.failures.len() {
+            true => result.push_str(&format!("First {} of {} unparsed lines:\n", validation.failures.len(), validation.unparsed())),
+            false => result.push_str("Unparsed lines:\n"),
+        }
+        for (number, line) in &validation.failures {
+            result.push_str(&format!("{:>8}: {}\n", number, line));
+        }
+    }
+
+    if !validation.by_kind.is_empty() {
+        result.push_str("Failures by kind:\n");
+        for (kind, count) in &validation.by_kind {
+            result.push_str(&format!("{:>8} {}\n", count, kind));
+        }
+    }
+
+    let verdict = match validation.passed(max_unparsed_pct) {
+        true => "PASS".green(),
+        false => "FAIL".red(),
+    };
+    let verdict = if color { verdict.bold().to_string() } else { verdict.clear().to_string() };
+    result.push_str(&format!("{}: {:.2}% unparsed, {:.2}% allowed\n", verdict, validation.unparsed_pct(), max_unparsed_pct));
+    result
+}
+
+pub fn output_validation_json(validation: &Validation, max_unparsed_pct: f64) -> String {
+    let mut value = serde_json::to_value(validation).unwrap();
+    value["unparsed"] = validation.unparsed().into();
+    value["unparsed_pct"] = validation.unparsed_pct().into();
+    value["max_unparsed_pct"] = max_unparsed_pct.into();
+    value["passed"] = validation.passed(max_unparsed_pct).into();
+    value["failures"] = validation.failures.iter()
+        .map(|(number, line)| serde_json::json!({ "line": number, "content": line }))
+        .collect::<Vec<serde_json::Value>>()
+        .into();
+    serde_json::to_string(&value).unwrap()
+}
@@ -0,0 +1,127 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use colored::Colorize;
+
+use l8r::HaproxyLogEntry;
+use crate::filter::Filter;
+use crate::stats::percentile;
+use crate::utils::bold;
+
+const REFRESH: Duration = Duration::from_secs(1);
+const TOP_BACKENDS: usize = 5;
+
+// What the dashboard needs of an entry, stamped with when it was read
+struct Sample {
+    at: Instant,
+    total: u64,
+    error: bool,
+    backend: String,
+}
+
+impl Sample {
+    fn new(entry: &HaproxyLogEntry) -> Sample {
+        Sample {
+            at: Instant::now(),
+            total: entry.timers.total,
+            error: entry.is_error(),
+            backend: entry.backend_name.to_string(),
+        }
+    }
+}
+
+// The entries read within the last `window`, by the time they were read rather than logged
+struct Window {
+    window: Duration,
+    samples: VecDeque<Sample>,
+    started: Instant,
+}
+
+impl Window {
+    fn prune(&mut self, now: Instant) {
+        while self.samples.front().is_some_and(|s| now.duration_since(s.at) > self.window) {
+            self.samples.pop_front();
+        }
+    }
+
+    fn render(&self, now: Instant, color: bool) -> String {
+        // Until a whole window has passed, rates are over the time watched so far
+        let span = now.duration_since(self.started).min(self.window).as_secs_f64().max(1.0);
+        let count = self.samples.len() as u64;
+        let errors = self.samples.iter().filter(|s| s.error).count() as u64;
+        let mut totals: Vec<u64> = self.samples.iter().map(|s| s.total).collect();
+        totals.sort_unstable();
+
+        let mut result = bold(&format!("l8r --watch, last {}s", self.window.as_secs()), color);
+        result.push_str("\n\n");
+        result.push_str(&format!("{:<16} {:>10.2}\n", "Requests/s", count as f64 / span));
+        let error_rate = match count {
+            0 => "-".to_string(),
+            count => format!("{:.2}%", errors as f64 / count as f64 * 100.0),
+        };
+        let error_rate = format!("{:>10}", error_rate);
+        result.push_str(&format!("{:<16} {}\n", "Error rate", match color && errors > 0 {
+            true => error_rate.red().to_string(),
+            false => error_rate,
+        }));
+        result.push_str(&format!("{:<16} {:>10}\n", "p95 latency", percentile(&totals, 95.0).map_or("-".to_string(), |p| format!("{}ms", p))));
+
+        let mut backends: HashMap<&str, (u64, u64)> = HashMap::new();
+        for sample in &self.samples {
+            let (requests, errors) = backends.entry(&sample.backend).or_default();
+            *requests += 1;
+            if sample.error {
+                *errors += 1;
+            }
+        }
+        let mut backends: Vec<(&str, (u64, u64))> = backends.into_iter().collect();
+        backends.sort_by(|a, b| b.1.0.cmp(&a.1.0).then(a.0.cmp(b.0)));
+        backends.truncate(TOP_BACKENDS);
+        let width = backends.iter().map(|(b, _)| b.len()).chain(["Backend".len()]).max().unwrap_or(0);
+
+        result.push('\n');
+        result.push_str(&bold(&format!("{:<width$} {:>10} {:>10}", "Backend", "Requests/s", "Errors", width = width), color));
+        result.push('\n');
+        for (backend, (requests, errors)) in backends {
+            result.push_str(&format!("{:<width$} {:>10.2} {:>10}\n", backend, requests as f64 / span, errors, width = width));
+        }
+        result
+    }
+}
+
+// Reads on its own thread so the dashboard keeps redrawing while no lines come in. Runs
+// until interrupted, as the followed input never ends
+pub fn run_watch(lines: Box<dyn Iterator<Item = String> + Send>, filter: &Filter, window: Duration, color: bool) {
+    let state = Mutex::new(Window { window, samples: VecDeque::new(), started: Instant::now() });
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for line in lines {
+                if !filter.matches_line(&line) {
+                    continue
+                }
+                let mapped = filter.map_line(&line);
+                if let Ok(mut entry) = filter.parse(&line, &mapped) {
+                    if filter.matches(&entry) {
+                        filter.normalize(&mut entry);
+                        state.lock().unwrap().samples.push_back(Sample::new(&entry));
+                    }
+                }
+            }
+        });
+
+        loop {
+            let now = Instant::now();
+            let dashboard = {
+                let mut state = state.lock().unwrap();
+                state.prune(now);
+                state.render(now, color)
+            };
+            // Home the cursor and clear the screen, then draw over it
+            print!("\x1b[H\x1b[2J{}", dashboard);
+            std::io::Write::flush(&mut std::io::stdout()).ok();
+            thread::sleep(REFRESH);
+        }
+    });
+}
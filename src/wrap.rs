@@ -0,0 +1,94 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use l8r::haproxy::{RE, TCP_RE};
+
+// Continuation lines joined onto a fragment before it's given up on
+const MAX_CONTINUATIONS: usize = 8;
+
+static ENTRY_START: Lazy<Regex> = regex_static::lazy_regex!(r#"^\p{L}{3,5}\.?\s+\d{1,2}\s+[0-9:]{8}\s"#);
+
+// A line that starts like an entry but stops before the closing quote of its request
+fn looks_truncated(line: &str) -> bool {
+    ENTRY_START.is_match(line) && !line.ends_with('"') && !RE.is_match(line) && !TCP_RE.is_match(line)
+}
+
+// Rejoins entries that a syslog transport wrapped over several lines
+pub struct JoinWrapped<I: Iterator<Item = String>> {
+    inner: I,
+    // A line read while completing a fragment, which belongs to the next entry
+    held: Option<String>,
+}
+
+impl<I: Iterator<Item = String>> JoinWrapped<I> {
+    pub fn new(inner: I) -> Self {
+        JoinWrapped { inner, held: None }
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        self.held.take().or_else(|| self.inner.next())
+    }
+}
+
+impl<I: Iterator<Item = String>> Iterator for JoinWrapped<I> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let mut line = self.next_line()?;
+        if !looks_truncated(&line) {
+            return Some(line);
+        }
+
+        for _ in 0..MAX_CONTINUATIONS {
+            let continuation = match self.inner.next() {
+                Some(continuation) => continuation,
+                None => break,
+            };
+            if ENTRY_START.is_match(&continuation) {
+                self.held = Some(continuation);
+                break;
+            }
+
+            line.push_str(&continuation);
+            if RE.is_match(&line) {
+                break;
+            }
+        }
+        // Whatever couldn't be completed is passed on and fails to parse as before
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = include_str!("../tests/fixtures/httplog.log");
+
+    fn join(lines: &[&str]) -> Vec<String> {
+        JoinWrapped::new(lines.iter().map(|line| line.to_string())).collect()
+    }
+
+    #[test]
+    fn joins_continuation_lines() {
+        let (start, rest) = LINE.split_at(120);
+        let (middle, end) = rest.split_at(40);
+        assert_eq!(join(&[start, middle, end, LINE]), vec![LINE, LINE]);
+    }
+
+    #[test]
+    fn gives_up_after_max_continuations() {
+        let start = &LINE[..120];
+        let mut lines = vec![start];
+        lines.extend(["x"; MAX_CONTINUATIONS + 1]);
+        let joined = join(&lines);
+        assert_eq!(joined[0], format!("{}{}", start, "x".repeat(MAX_CONTINUATIONS)));
+        assert_eq!(joined[1..], ["x"]);
+    }
+
+    #[test]
+    fn new_entry_flushes_pending_fragment() {
+        let start = &LINE[..120];
+        assert_eq!(join(&[start, LINE]), vec![start, LINE]);
+    }
+}
@@ -0,0 +1,74 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+const LINE: &str = include_str!("fixtures/httplog.log");
+
+fn l8r(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_l8r"))
+        .args(args)
+        .arg("--no-pager")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn context_is_refused_with_buffered_outputs() {
+    for output in ["ascii-table", "columns", "html", "parquet"] {
+        let result = l8r(&["-C", "1", "--status", "500", "-o", output], LINE);
+        assert_eq!(result.status.code(), Some(1), "--output {}", output);
+        assert!(String::from_utf8_lossy(&result.stderr).contains("-C, -A and -B don't work"), "--output {}", output);
+    }
+}
+
+#[test]
+fn context_prints_entries_around_matches() {
+    let input = format!("{}\n{}\n", LINE, LINE.replace(" 200 ", " 500 "));
+    let result = l8r(&["-B", "1", "--status", "500", "-o", "raw", "--quiet"], &input);
+    assert!(result.status.success());
+    assert_eq!(String::from_utf8_lossy(&result.stdout).lines().count(), 2);
+}
+
+#[test]
+fn aggregations_report_line_counts() {
+    let input = format!("{}\nnot a log line\n{}\n", LINE, LINE.replace("GET", "POST"));
+    let result = l8r(&["--count"], &input);
+    assert_eq!(String::from_utf8_lossy(&result.stdout).trim(), "2");
+    assert!(String::from_utf8_lossy(&result.stderr).contains("Parsed 2 lines, 1 failed to match"));
+
+    let result = l8r(&["--count", "-m", "POST"], &input);
+    assert_eq!(String::from_utf8_lossy(&result.stdout).trim(), "1");
+    assert!(String::from_utf8_lossy(&result.stderr).contains("Parsed 1 lines, 0 failed to match, 2 skipped by --matcher"));
+}
+
+#[test]
+fn passthrough_prints_preset_lines_as_read() {
+    let clf = r#"Feb  6 12:12:56 localhost haproxy[14387]: 10.0.1.2 - - [06/Feb/2009:12:12:51 +0100] "GET /index.html HTTP/1.1" 200 2750 "" "" 33317 661 "httpin" "static" "srv1" 10 0 30 69 109 ---- 1 1 1 1 0 0 0 "" "" "" """#;
+    let input = format!("{}\n{}\n", clf, clf.replace(" 200 ", " 503 "));
+    let result = l8r(&["--preset", "clf", "-o", "passthrough", "--quiet"], &input);
+    assert!(result.status.success());
+    assert_eq!(String::from_utf8_lossy(&result.stdout), input);
+}
+
+#[test]
+fn failures_are_emitted_as_json() {
+    let path = std::env::temp_dir().join(format!("l8r-failures-{}.jsonl", std::process::id()));
+    let overflow = LINE.replace("0/0/9/17/26", "0/0/1/11/99999999999999999999");
+    let input = format!("{}\nnot a log line\n{}\n", LINE, overflow);
+    let result = l8r(&["-o", "raw", "--quiet", "--emit-failures", path.to_str().unwrap()], &input);
+    let emitted = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    assert!(result.status.success());
+
+    // Written by whichever worker parsed the line, so in no particular order
+    let mut failures: Vec<serde_json::Value> = emitted.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    failures.sort_by_key(|failure| failure["error"].to_string());
+    assert_eq!(failures, [
+        serde_json::json!({ "error": "regex_no_match", "message": "Line doesn't match any supported log format", "line": "not a log line" }),
+        serde_json::json!({ "error": "timers", "message": "Failed to parse timers: 0/0/1/11/99999999999999999999", "line": overflow }),
+    ]);
+}
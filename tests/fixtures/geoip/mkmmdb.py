@@ -0,0 +1,94 @@
+# Writes the MaxMind DB fixtures the geoip tests look up addresses in
+import ipaddress, os, struct, sys
+
+def ctrl(kind, size):
+    out = b''
+    if kind > 7:
+        first, ext = 0, bytes([kind - 7])
+    else:
+        first, ext = kind << 5, b''
+    if size < 29: return bytes([first | size]) + ext
+    if size < 285: return bytes([first | 29]) + ext + bytes([size - 29])
+    if size < 65821: return bytes([first | 30]) + ext + (size - 285).to_bytes(2, 'big')
+    return bytes([first | 31]) + ext + (size - 65821).to_bytes(3, 'big')
+
+def enc(v):
+    if isinstance(v, Ptr):
+        p = v.off
+        assert p < 2048
+        return bytes([0x20 | (p >> 8), p & 0xff])
+    if isinstance(v, bool): return ctrl(14, int(v))
+    if isinstance(v, str):
+        b = v.encode(); return ctrl(2, len(b)) + b
+    if isinstance(v, float): return ctrl(3, 8) + struct.pack('>d', v)
+    if isinstance(v, int):
+        b = v.to_bytes((v.bit_length() + 7) // 8, 'big') if v else b''
+        return ctrl(6 if v < 2**32 else 9, len(b)) + b
+    if isinstance(v, list): return ctrl(11, len(v)) + b''.join(enc(x) for x in v)
+    if isinstance(v, dict): return ctrl(7, len(v)) + b''.join(enc(k) + enc(x) for k, x in v.items())
+class Ptr:
+    def __init__(s, off): s.off = off
+
+def build(networks, record_size, ip_version=6):
+    data = b''
+    shared = enc({"iso_code": "US", "names": {"en": "United States"}})
+    data += shared  # offset 0, referenced by pointer
+    offsets = []
+    for net, rec in networks:
+        offsets.append(len(data))
+        data += enc(rec)
+    root = {}
+    for i, (net, rec) in enumerate(networks):
+        n = ipaddress.ip_network(net)
+        if ip_version == 6 and n.version == 4:
+            bits = 96 * '0' + format(int(n.network_address), '032b'); plen = 96 + n.prefixlen
+        else:
+            width = 128 if n.version == 6 else 32
+            bits = format(int(n.network_address), '0%db' % width); plen = n.prefixlen
+        node = root
+        for b in bits[:plen - 1]:
+            node = node.setdefault(b, {})
+        node[bits[plen - 1]] = ('data', offsets[i])
+    nodes = []
+    def number(node):
+        idx = len(nodes); nodes.append(node)
+        for b in '01':
+            c = node.get(b)
+            if isinstance(c, dict): number(c)
+        return idx
+    ids = {}
+    order = []
+    def walk(node):
+        ids[id(node)] = len(order); order.append(node)
+        for b in '01':
+            c = node.get(b)
+            if isinstance(c, dict): walk(c)
+    walk(root)
+    count = len(order)
+    def rec_val(c):
+        if c is None: return count
+        if isinstance(c, tuple): return count + 16 + c[1]
+        return ids[id(c)]
+    tree = b''
+    for node in order:
+        l, r = rec_val(node.get('0')), rec_val(node.get('1'))
+        if record_size == 24: tree += l.to_bytes(3, 'big') + r.to_bytes(3, 'big')
+        elif record_size == 32: tree += l.to_bytes(4, 'big') + r.to_bytes(4, 'big')
+        else:
+            tree += l.to_bytes(4, 'big')[1:] + bytes([((l >> 24) << 4) | (r >> 24)]) + r.to_bytes(4, 'big')[1:]
+    meta = enc({"node_count": count, "record_size": record_size, "ip_version": ip_version, "database_type": "Test",
+                "languages": ["en"], "binary_format_major_version": 2, "binary_format_minor_version": 0, "build_epoch": 1,
+                "description": {"en": "test"}})
+    return tree + b'\0' * 16 + data + b'\xab\xcd\xefMaxMind.com' + meta
+
+country = [
+    ("10.0.0.0/24", {"country": Ptr(0), "score": 1.5, "ok": True, "tags": ["a", "b"]}),
+    ("10.0.1.0/24", {"country": {"iso_code": "DE"}}),
+    ("2001:db8::/32", {"country": {"iso_code": "FI"}}),
+]
+asn = [("10.0.0.0/8", {"autonomous_system_number": 64512, "autonomous_system_organization": "Example"})]
+HERE = os.path.dirname(os.path.abspath(__file__))
+for size in (24, 28, 32):
+    open(os.path.join(HERE, 'country%d.mmdb' % size), 'wb').write(build(country, size))
+open(os.path.join(HERE, 'asn.mmdb'), 'wb').write(build(asn, 24))
+open(os.path.join(HERE, 'asn4.mmdb'), 'wb').write(build(asn, 28, ip_version=4))